@@ -0,0 +1,45 @@
+/* C API of the multi_reader crate, built with the "ffi" feature. */
+
+#ifndef MULTI_READER_H
+#define MULTI_READER_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Reads up to `len` bytes into `buf`: returns the number of bytes read, 0 at
+ * EOF or a negative value on error. */
+typedef ptrdiff_t (*multi_reader_read_fn)(void *ctx, unsigned char *buf, size_t len);
+typedef void (*multi_reader_close_fn)(void *ctx);
+
+struct multi_reader_source {
+    void *ctx;
+    multi_reader_read_fn read;
+    /* May be NULL. Called once the chain is done with the source. */
+    multi_reader_close_fn close;
+};
+
+/* Fills in `source` and returns non-zero while there are sources left. */
+typedef int (*multi_reader_next_fn)(void *ctx, struct multi_reader_source *source);
+
+struct multi_reader;
+
+struct multi_reader *multi_reader_new(multi_reader_next_fn next, void *ctx);
+
+/* Returns the number of bytes read, 0 at the end of the chain or -1 on
+ * error. */
+ptrdiff_t multi_reader_read(struct multi_reader *reader, unsigned char *buf, size_t len);
+
+/* Index of the source the last read was served from, or SIZE_MAX if
+ * `reader` is NULL. */
+size_t multi_reader_source_index(const struct multi_reader *reader);
+
+void multi_reader_free(struct multi_reader *reader);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,150 @@
+//! Checking that a chain of fixed-size records holds whole records only.
+//!
+//! `MultiReader::record_size` fails reads once a source turns out not to
+//! be made of whole records, which catches truncated or corrupted segment
+//! files before their bytes shift every record after them:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//!
+//! fn main() {
+//!     let segments = vec![&b"aaaabbbb"[..], &b"ccc"[..], &b"dddd"[..]];
+//!     let mut reader = MultiReader::new(segments.into_iter()).record_size(4);
+//!     let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+//!     assert_eq!(err.to_string(), "source 1 ends 3 bytes into a 4-byte record");
+//! }
+//! ```
+//!
+//! The error comes with the first read past the end of the faulty source,
+//! whose bytes it replaces. With `across_sources` records may span sources,
+//! and only the chain as a whole has to end at the end of a record.
+
+use std::io;
+use std::io::Read;
+
+use MultiReader;
+
+/// Reader behind `MultiReader::record_size`.
+pub struct Aligned<R, I> {
+    inner: MultiReader<R, I>,
+    size: u64,
+    per_source: bool,
+    // Source index and offset after the last read.
+    last: (usize, u64),
+    total: u64,
+    failed: Option<(io::ErrorKind, String)>,
+}
+
+impl<R: Read, I: Iterator<Item = R>> Aligned<R, I> {
+    pub fn new(inner: MultiReader<R, I>, size: usize) -> Aligned<R, I> {
+        assert!(size > 0, "records must not be empty");
+        Aligned {
+            inner,
+            size: size as u64,
+            per_source: true,
+            last: (0, 0),
+            total: 0,
+            failed: None,
+        }
+    }
+
+    /// Lets records span sources, checking only where the chain ends.
+    pub fn across_sources(mut self) -> Aligned<R, I> {
+        self.per_source = false;
+        self
+    }
+
+    pub fn get_ref(&self) -> &MultiReader<R, I> {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> MultiReader<R, I> {
+        self.inner
+    }
+
+    /// Checks the length of the source read last, now that it has ended.
+    fn check_source(&mut self) -> io::Result<()> {
+        let (index, len) = self.last;
+        let rest = len % self.size;
+        if self.per_source && rest != 0 {
+            let msg = format!("source {} ends {} bytes into a {}-byte record", index, rest, self.size);
+            return self.fail(io::ErrorKind::InvalidData, msg);
+        }
+        Ok(())
+    }
+
+    fn fail(&mut self, kind: io::ErrorKind, msg: String) -> io::Result<()> {
+        let err = io::Error::new(kind, msg.clone());
+        self.failed = Some((kind, msg));
+        Err(err)
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Read for Aligned<R, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some((kind, ref msg)) = self.failed {
+            return Err(io::Error::new(kind, msg.clone()));
+        }
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            let index = self.inner.source_index();
+            if index != self.last.0 {
+                self.check_source()?;
+            }
+            self.last = (index, self.inner.source_offset());
+            self.total += n as u64;
+        } else if !buf.is_empty() && self.inner.is_finished() {
+            self.check_source()?;
+            let rest = self.total % self.size;
+            if rest != 0 {
+                let msg = format!("the chain ends {} bytes into a {}-byte record", rest, self.size);
+                self.fail(io::ErrorKind::UnexpectedEof, msg)?;
+            }
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use MultiReader;
+
+    #[test]
+    fn test_whole_records() {
+        let sources = vec![&b"abcd"[..], &b""[..], &b"efghijkl"[..]];
+        let mut m = MultiReader::new(sources.into_iter()).record_size(4);
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out.len(), 12);
+    }
+
+    #[test]
+    fn test_last_source_is_checked() {
+        let mut m = MultiReader::new(vec![&b"abcd"[..], &b"ef"[..]].into_iter()).record_size(4);
+        let mut out = Vec::new();
+        let err = m.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(out, b"abcdef");
+        // The error sticks.
+        assert!(m.read(&mut [0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_across_sources() {
+        let mut m = MultiReader::new(vec![&b"abc"[..], &b"d"[..]].into_iter())
+            .record_size(2)
+            .across_sources();
+        m.read_to_end(&mut Vec::new()).unwrap();
+
+        let mut m = MultiReader::new(vec![&b"abc"[..], &b"de"[..]].into_iter())
+            .record_size(2)
+            .across_sources();
+        let err = m.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), "the chain ends 1 bytes into a 2-byte record");
+    }
+}
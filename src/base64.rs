@@ -0,0 +1,247 @@
+//! Streaming base64 decoding.
+//!
+//! `Decoder` can wrap every source separately, so each one may carry its own
+//! padding:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::{base64, MultiReader};
+//!
+//! fn main() {
+//!     let parts = vec![&b"aGVs"[..], &b"bG8="[..], &b"IQ=="[..]];
+//!     let mut reader = MultiReader::new(parts.into_iter().map(base64::Decoder::new));
+//!     let mut s = String::new();
+//!     reader.read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "hello!");
+//! }
+//! ```
+//!
+//! or the whole concatenation, in which case a padded group simply ends the
+//! current payload and decoding carries on with the next one.
+
+use std::io;
+use std::io::Read;
+
+const INPUT_SIZE: usize = 4096;
+
+pub struct Decoder<R> {
+    inner: R,
+    input: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    offset: u64,
+    quantum: [u8; 4],
+    filled: usize,
+    padding: usize,
+    output: [u8; 3],
+    out_pos: usize,
+    out_len: usize,
+    // Failure kept for the next read, so that the bytes decoded before it
+    // are returned first.
+    error: Option<io::Error>,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R) -> Decoder<R> {
+        Decoder {
+            inner,
+            input: vec![0; INPUT_SIZE].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+            offset: 0,
+            quantum: [0; 4],
+            filled: 0,
+            padding: 0,
+            output: [0; 3],
+            out_pos: 0,
+            out_len: 0,
+            error: None,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn push(&mut self, c: u8, at: u64) -> io::Result<()> {
+        match c {
+            b' ' | b'\t' | b'\r' | b'\n' => return Ok(()),
+            b'=' => {
+                if self.filled < 2 {
+                    return Err(invalid("unexpected base64 padding", at));
+                }
+                self.padding += 1;
+            }
+            _ => {
+                let value = match decode_byte(c) {
+                    Some(v) if self.padding == 0 => v,
+                    _ => return Err(invalid(&format!("invalid base64 byte 0x{:02x}", c), at)),
+                };
+                self.quantum[self.filled] = value;
+            }
+        }
+        self.filled += 1;
+        if self.filled == 4 {
+            self.flush(at)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, at: u64) -> io::Result<()> {
+        let data = self.filled - self.padding;
+        if data == 1 {
+            return Err(invalid("truncated base64 group", at));
+        }
+        let q = self.quantum;
+        self.output = [
+            q[0] << 2 | q[1] >> 4,
+            (q[1] & 0x0f) << 4 | q[2] >> 2,
+            (q[2] & 0x03) << 6 | q[3],
+        ];
+        self.out_pos = 0;
+        self.out_len = data.saturating_sub(1);
+        self.quantum = [0; 4];
+        self.filled = 0;
+        self.padding = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let mut written = 0;
+        while written < buf.len() {
+            if self.out_pos < self.out_len {
+                buf[written] = self.output[self.out_pos];
+                self.out_pos += 1;
+                written += 1;
+                continue;
+            }
+            if self.pos == self.len {
+                if written > 0 {
+                    break;
+                }
+                self.offset += self.len as u64;
+                self.pos = 0;
+                self.len = self.inner.read(&mut self.input)?;
+                if self.len == 0 {
+                    if self.filled > 0 {
+                        let at = self.offset;
+                        self.flush(at)?;
+                        continue;
+                    }
+                    break;
+                }
+            }
+            let c = self.input[self.pos];
+            let at = self.offset + self.pos as u64;
+            self.pos += 1;
+            if let Err(e) = self.push(c, at) {
+                if written == 0 {
+                    return Err(e);
+                }
+                self.error = Some(e);
+                break;
+            }
+        }
+        Ok(written)
+    }
+}
+
+fn invalid(msg: &str, at: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{} at offset {}", msg, at))
+}
+
+fn decode_byte(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use super::Decoder;
+    use MultiReader;
+
+    fn decode<R: Read>(mut r: R) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        r.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_decode_padded_and_unpadded() {
+        assert_eq!(decode(Decoder::new(&b"aGVsbG8="[..])).unwrap(), b"hello");
+        assert_eq!(decode(Decoder::new(&b"aGVsbG8"[..])).unwrap(), b"hello");
+        assert_eq!(decode(Decoder::new(&b"aGk="[..])).unwrap(), b"hi");
+        assert_eq!(decode(Decoder::new(&b""[..])).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_mime_line_breaks() {
+        let src = &b"aGVs\r\nbG8g\r\nd29y\r\nbGQ=\r\n"[..];
+        assert_eq!(decode(Decoder::new(src)).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn test_tiny_output_buffer() {
+        let mut d = Decoder::new(&b"aGVsbG8="[..]);
+        let mut out = Vec::new();
+        let mut buf = [0; 1];
+        while d.read(&mut buf).unwrap() == 1 {
+            out.push(buf[0]);
+        }
+        assert_eq!(out, b"hello");
+    }
+
+    #[test]
+    fn test_per_source_padding() {
+        let sources = vec![&b"aGk="[..], &b"IQ=="[..], &b""[..], &b"eW8"[..]];
+        let m = MultiReader::new(sources.into_iter().map(Decoder::new));
+        assert_eq!(decode(m).unwrap(), b"hi!yo");
+    }
+
+    #[test]
+    fn test_padding_inside_concatenation() {
+        let sources = vec![&b"aGk="[..], &b"IQ"[..], &b"=="[..], &b"eW8="[..]];
+        let m = MultiReader::new(sources.into_iter());
+        assert_eq!(decode(Decoder::new(m)).unwrap(), b"hi!yo");
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let err = decode(Decoder::new(&b"aGk*"[..])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "invalid base64 byte 0x2a at offset 3");
+
+        let err = decode(Decoder::new(&b"aGk=a"[..])).unwrap_err();
+        assert_eq!(err.to_string(), "truncated base64 group at offset 5");
+
+        let err = decode(Decoder::new(&b"a==="[..])).unwrap_err();
+        assert_eq!(err.to_string(), "unexpected base64 padding at offset 1");
+
+        let err = decode(Decoder::new(&b"aG=k"[..])).unwrap_err();
+        assert_eq!(err.to_string(), "invalid base64 byte 0x6b at offset 3");
+    }
+
+    #[test]
+    fn test_bytes_before_an_error_are_returned() {
+        let mut d = Decoder::new(&b"aGVsbG8g*"[..]);
+        let mut buf = [0; 16];
+        assert_eq!(d.read(&mut buf).unwrap(), 6);
+        assert_eq!(&buf[..6], b"hello ");
+        let err = d.read(&mut buf).unwrap_err();
+        assert_eq!(err.to_string(), "invalid base64 byte 0x2a at offset 8");
+    }
+}
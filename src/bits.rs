@@ -0,0 +1,116 @@
+//! Reading a stream bit by bit.
+//!
+//! `BitReader` reads values of up to 64 bits, most significant bit first,
+//! from any reader, so that a value can start in one source of a chain and
+//! end in the next:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use multi_reader::MultiReader;
+//! use multi_reader::bits::BitReader;
+//!
+//! fn main() {
+//!     let captures = vec![&[0b1010_0001u8][..], &[0b1100_0000u8][..]];
+//!     let mut bits = BitReader::new(MultiReader::new(captures.into_iter()));
+//!     assert_eq!(bits.read_bits(3).unwrap(), 0b101);
+//!     assert_eq!(bits.read_bits(7).unwrap(), 0b0_0001_11);
+//!     assert!(!bits.read_bit().unwrap());
+//! }
+//! ```
+//!
+//! Every byte is read from the inner reader on its own, so a chain of files
+//! is best wrapped in a `BufferedMultiReader` first.
+
+use std::io;
+use std::io::Read;
+
+pub struct BitReader<R> {
+    inner: R,
+    byte: u8,
+    // Bits of `byte` not read yet, its lowest ones.
+    left: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(inner: R) -> BitReader<R> {
+        BitReader {
+            inner,
+            byte: 0,
+            left: 0,
+        }
+    }
+
+    /// Reads an `n`-bit value, `n` being at most 64. The stream ending
+    /// within it fails with `UnexpectedEof`.
+    pub fn read_bits(&mut self, n: u32) -> io::Result<u64> {
+        assert!(n <= 64, "cannot read more than 64 bits at once");
+        let mut value = 0u64;
+        let mut wanted = n;
+        while wanted > 0 {
+            if self.left == 0 {
+                let mut buf = [0; 1];
+                self.inner.read_exact(&mut buf)?;
+                self.byte = buf[0];
+                self.left = 8;
+            }
+            let take = ::std::cmp::min(wanted, self.left);
+            let bits = (self.byte >> (self.left - take)) as u64 & ((1 << take) - 1);
+            value = (value << take) | bits;
+            self.left -= take;
+            wanted -= take;
+        }
+        Ok(value)
+    }
+
+    pub fn read_bit(&mut self) -> io::Result<bool> {
+        self.read_bits(1).map(|bit| bit == 1)
+    }
+
+    /// Skips the bits left of the current byte, so that the next read
+    /// starts at a byte boundary.
+    pub fn align(&mut self) {
+        self.left = 0;
+    }
+
+    /// Whether the next read starts at a byte boundary.
+    pub fn is_aligned(&self) -> bool {
+        self.left == 0
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// The inner reader, with the bits left of the current byte lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::BitReader;
+    use MultiReader;
+
+    #[test]
+    fn test_values_across_sources() {
+        let sources = vec![&[0xab, 0xcd][..], &[][..], &[0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab][..]];
+        let mut bits = BitReader::new(MultiReader::new(sources.into_iter()));
+        assert_eq!(bits.read_bits(4).unwrap(), 0xa);
+        assert_eq!(bits.read_bits(0).unwrap(), 0);
+        assert_eq!(bits.read_bits(12).unwrap(), 0xbcd);
+        assert!(bits.is_aligned());
+        assert_eq!(bits.read_bits(5).unwrap(), 0b11101);
+        bits.align();
+        assert_eq!(bits.read_bits(64).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let bytes = [0x80, 0, 0, 0, 0, 0, 0, 0x01, 0xff];
+        let mut bits = BitReader::new(MultiReader::new(bytes.chunks(3)));
+        assert!(bits.read_bit().unwrap());
+        assert_eq!(bits.read_bits(64).unwrap(), 0b11);
+        let mut bits = BitReader::new(MultiReader::new([0xff; 9].chunks(2)));
+        bits.read_bits(4).unwrap();
+        assert_eq!(bits.read_bits(64).unwrap(), u64::MAX);
+    }
+}
@@ -0,0 +1,248 @@
+//! A chain that buffers each of its sources.
+//!
+//! Wrapping a whole chain in a `BufReader` buffers across source boundaries
+//! and loses track of which source the bytes came from. A
+//! `BufferedMultiReader` instead fills its buffer from one source at a time,
+//! reusing the same buffer for all of them, so that many small reads only
+//! take a few reads from the sources:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::BufRead;
+//! use multi_reader::MultiReader;
+//! use multi_reader::buffered::BufferedMultiReader;
+//!
+//! fn main() {
+//!     let chain = MultiReader::new(vec![&b"one\ntw"[..], &b"o\n"[..]].into_iter());
+//!     let mut reader = BufferedMultiReader::new(chain);
+//!     assert_eq!(reader.fill_buf().unwrap(), b"one\ntw");
+//!     reader.consume(6);
+//!     assert_eq!(reader.fill_buf().unwrap(), b"o\n");
+//!     assert_eq!(reader.source_index(), 1);
+//! }
+//! ```
+//!
+//! Parsers that need every `fill_buf` to hold bytes of a single source rely
+//! on that default. Others get fuller buffers from `Boundary::Coalesce`.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::{BufRead, Read};
+
+use pool;
+use MultiReader;
+
+/// Whether a buffer may hold bytes of more than one source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// `fill_buf` returns bytes of a single source. The default.
+    Stop,
+    /// `fill_buf` reads on past the end of a source until the buffer is
+    /// full or the chain ends, which can block on a source that has no
+    /// data yet.
+    Coalesce,
+}
+
+/// A `MultiReader` whose reads are buffered source by source.
+pub struct BufferedMultiReader<R, I> {
+    inner: MultiReader<R, I>,
+    buf: Vec<u8>,
+    pos: usize,
+    filled: usize,
+    boundary: Boundary,
+    // (end position in `buf`, source index, source offset at the end)
+    runs: VecDeque<(usize, usize, u64)>,
+    // Failure of a read after others had filled part of the buffer.
+    error: Option<io::Error>,
+}
+
+impl<R: Read, I: Iterator<Item = R>> BufferedMultiReader<R, I> {
+    pub fn new(inner: MultiReader<R, I>) -> BufferedMultiReader<R, I> {
+        BufferedMultiReader::with_capacity(pool::DEFAULT_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: MultiReader<R, I>) -> BufferedMultiReader<R, I> {
+        assert!(capacity > 0, "the buffer must not be empty");
+        BufferedMultiReader {
+            inner,
+            buf: vec![0; capacity],
+            pos: 0,
+            filled: 0,
+            boundary: Boundary::Stop,
+            runs: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    pub fn boundary(mut self, boundary: Boundary) -> BufferedMultiReader<R, I> {
+        self.boundary = boundary;
+        self
+    }
+
+    /// Index of the source of the next byte to be read, or of the last one
+    /// read while nothing is buffered.
+    pub fn source_index(&self) -> usize {
+        self.position().0
+    }
+
+    /// Offset within that source of the next byte to be read.
+    pub fn source_offset(&self) -> u64 {
+        self.position().1
+    }
+
+    fn position(&self) -> (usize, u64) {
+        match self.runs.iter().find(|run| run.0 > self.pos) {
+            // Separator bytes are at offset 0 of the source they come before.
+            Some(&(end, index, offset)) => (index, offset.saturating_sub((end - self.pos) as u64)),
+            None => (self.inner.source_index(), self.inner.source_offset()),
+        }
+    }
+
+    /// Bytes buffered but not read yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    pub fn get_ref(&self) -> &MultiReader<R, I> {
+        &self.inner
+    }
+
+    /// The chain, with any buffered bytes lost.
+    pub fn into_inner(self) -> MultiReader<R, I> {
+        self.inner
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Read for BufferedMultiReader<R, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Large reads gain nothing from going through the buffer.
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            return self.inner.read(buf);
+        }
+        let n = {
+            let available = self.fill_buf()?;
+            let n = ::std::cmp::min(buf.len(), available.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> BufRead for BufferedMultiReader<R, I> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.filled {
+            if let Some(e) = self.error.take() {
+                return Err(e);
+            }
+            self.pos = 0;
+            self.filled = 0;
+            self.runs.clear();
+            while self.filled < self.buf.len() {
+                // A read of the chain never spans two sources.
+                let n = match self.inner.read(&mut self.buf[self.filled..]) {
+                    Ok(n) => n,
+                    // Bytes read so far are returned first.
+                    Err(e) if self.filled > 0 => {
+                        self.error = Some(e);
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                };
+                if n == 0 {
+                    break;
+                }
+                self.filled += n;
+                self.runs.push_back((self.filled, self.inner.source_index(), self.inner.source_offset()));
+                if self.boundary == Boundary::Stop {
+                    break;
+                }
+            }
+        }
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = ::std::cmp::min(self.pos + amt, self.filled);
+        while self.runs.front().is_some_and(|run| run.0 <= self.pos) {
+            self.runs.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Read};
+    use super::{Boundary, BufferedMultiReader};
+    use testing::MaybeErrReader;
+    use MultiReader;
+
+    #[test]
+    fn test_small_reads_are_buffered() {
+        let sources = vec![MaybeErrReader::good(&b"abcdef"[..]), MaybeErrReader::good(&b"gh"[..])];
+        let mut m = BufferedMultiReader::with_capacity(4, MultiReader::new(sources.into_iter()));
+        let mut out = Vec::new();
+        let mut byte = [0; 1];
+        while m.read(&mut byte).unwrap() == 1 {
+            out.push(byte[0]);
+            if out.len() == 5 {
+                assert_eq!((m.source_index(), m.source_offset()), (0, 5));
+            }
+        }
+        assert_eq!(out, b"abcdefgh");
+        assert_eq!(m.source_index(), 2);
+    }
+
+    #[test]
+    fn test_fill_buf_stops_at_boundaries() {
+        let chain = MultiReader::new(vec![&b"ab"[..], &b""[..], &b"cd"[..]].into_iter());
+        let mut m = BufferedMultiReader::new(chain);
+        assert_eq!(m.fill_buf().unwrap(), b"ab");
+        assert_eq!((m.source_index(), m.source_offset()), (0, 0));
+        m.consume(2);
+        assert_eq!(m.fill_buf().unwrap(), b"cd");
+        assert_eq!(m.source_index(), 2);
+        let lines: Vec<String> = m.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["cd"]);
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let chain = MultiReader::new(vec![&b"ab"[..], &b""[..], &b"cde"[..]].into_iter());
+        let mut m = BufferedMultiReader::with_capacity(4, chain).boundary(Boundary::Coalesce);
+        assert_eq!(m.fill_buf().unwrap(), b"abcd");
+        m.consume(1);
+        assert_eq!((m.source_index(), m.source_offset()), (0, 1));
+        m.consume(1);
+        assert_eq!((m.source_index(), m.source_offset()), (2, 0));
+        m.consume(2);
+        assert_eq!(m.fill_buf().unwrap(), b"e");
+        assert_eq!((m.source_index(), m.source_offset()), (2, 2));
+    }
+
+    #[test]
+    fn test_coalesce_keeps_errors() {
+        let sources = vec![MaybeErrReader::good(&b"ab"[..]), MaybeErrReader::broken(&b"cd"[..], 0)];
+        let chain = MultiReader::new(sources.into_iter());
+        let mut m = BufferedMultiReader::new(chain).boundary(Boundary::Coalesce);
+        assert_eq!(m.fill_buf().unwrap(), b"ab");
+        m.consume(2);
+        assert!(m.fill_buf().is_err());
+        assert_eq!(m.fill_buf().unwrap(), b"cd");
+    }
+
+    #[test]
+    fn test_separators() {
+        let chain = MultiReader::new(vec![&b"ab"[..], &b"c"[..]].into_iter()).separator(b"--");
+        let mut m = BufferedMultiReader::with_capacity(3, chain);
+        assert_eq!(m.fill_buf().unwrap(), b"ab");
+        m.consume(2);
+        assert_eq!(m.fill_buf().unwrap(), b"--");
+        m.consume(1);
+        assert_eq!((m.source_index(), m.source_offset()), (1, 0));
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"-c");
+    }
+}
@@ -0,0 +1,169 @@
+//! Fluent configuration of a `MultiReader`.
+
+use std::io;
+use std::io::Read;
+use std::sync::Arc;
+
+use {EmptyRead, EmptyReadPolicy, MapEach, MultiReader, Separator};
+use pool;
+use pool::BufferPool;
+
+/// Collects the sources and options of a chain, see `MultiReader::builder`.
+///
+/// ```rust
+/// extern crate multi_reader;
+/// use std::io::Read;
+/// use multi_reader::{EmptyRead, MultiReader};
+///
+/// fn main() {
+///     let mut reader = MultiReader::builder(vec![&b"abc"[..], &b"def"[..]])
+///         .map_each(|_, r| r.take(2))
+///         .empty_read_policy(|_, _| EmptyRead::Advance)
+///         .build();
+///     let mut s = String::new();
+///     reader.read_to_string(&mut s).unwrap();
+///     assert_eq!(s, "abde");
+/// }
+/// ```
+pub struct MultiReaderBuilder<I> {
+    readers: I,
+    empty_read_policy: Option<EmptyReadPolicy>,
+    separator: Option<Separator>,
+    separator_len: usize,
+    buffer_size: usize,
+    pool: Option<Arc<BufferPool>>,
+    memory_limit: Option<usize>,
+}
+
+impl<R: Read, I: Iterator<Item = R>> MultiReaderBuilder<I> {
+    pub fn new<T>(readers: T) -> MultiReaderBuilder<I>
+        where T: IntoIterator<Item = R, IntoIter = I>
+    {
+        MultiReaderBuilder {
+            readers: readers.into_iter(),
+            empty_read_policy: None,
+            separator: None,
+            separator_len: 0,
+            buffer_size: pool::DEFAULT_SIZE,
+            pool: None,
+            memory_limit: None,
+        }
+    }
+
+    /// See `MultiReader::map_each`.
+    pub fn map_each<W, F>(self, f: F) -> MultiReaderBuilder<MapEach<I, F>>
+        where W: Read,
+              F: FnMut(usize, R) -> W
+    {
+        MultiReaderBuilder {
+            readers: MapEach {
+                readers: self.readers,
+                index: 0,
+                f,
+            },
+            empty_read_policy: self.empty_read_policy,
+            separator: self.separator,
+            separator_len: self.separator_len,
+            buffer_size: self.buffer_size,
+            pool: self.pool,
+            memory_limit: self.memory_limit,
+        }
+    }
+
+    /// See `MultiReader::empty_read_policy`.
+    pub fn empty_read_policy<F>(mut self, policy: F) -> MultiReaderBuilder<I>
+        where F: FnMut(usize, usize) -> EmptyRead + Send + 'static
+    {
+        self.empty_read_policy = Some(Box::new(policy));
+        self
+    }
+
+    /// See `MultiReader::separator`.
+    pub fn separator(mut self, bytes: &[u8]) -> MultiReaderBuilder<I> {
+        let bytes = bytes.to_vec();
+        self.separator_len = bytes.len();
+        self.separator = Some(Box::new(move |_| Box::new(io::Cursor::new(bytes.clone()))));
+        self
+    }
+
+    /// See `MultiReader::separator_with`.
+    pub fn separator_with<S, F>(mut self, mut f: F) -> MultiReaderBuilder<I>
+        where S: Read + Send + 'static,
+              F: FnMut(usize) -> S + Send + 'static
+    {
+        self.separator = Some(Box::new(move |index| Box::new(f(index))));
+        self.separator_len = 0;
+        self
+    }
+
+    /// See `MultiReader::buffer_size`.
+    pub fn buffer_size(mut self, size: usize) -> MultiReaderBuilder<I> {
+        self.buffer_size = size;
+        self
+    }
+
+    /// See `MultiReader::buffer_pool`.
+    pub fn buffer_pool(mut self, pool: Arc<BufferPool>) -> MultiReaderBuilder<I> {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// See `MultiReader::memory_limit`, checked against the other options
+    /// by `build`.
+    pub fn memory_limit(mut self, bytes: usize) -> MultiReaderBuilder<I> {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    pub fn build(self) -> MultiReader<R, I> {
+        let mut m = match self.pool {
+            Some(pool) => MultiReader::new(self.readers).buffer_pool(pool),
+            None => MultiReader::new(self.readers).buffer_size(self.buffer_size),
+        };
+        m.empty_read_policy = self.empty_read_policy;
+        m.separator = self.separator;
+        m.separator_len = self.separator_len;
+        match self.memory_limit {
+            Some(bytes) => m.memory_limit(bytes),
+            None => m,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use {EmptyRead, MultiReader};
+
+    #[test]
+    fn test_builder_defaults() {
+        let mut m = MultiReader::builder(vec![&b"a"[..], &b"b"[..]]).build();
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"ab");
+    }
+
+    #[test]
+    fn test_builder_separator() {
+        let mut m = MultiReader::builder(vec![&b"a"[..], &b"b"[..]]).separator(b"\n").build();
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"a\nb");
+    }
+
+    #[test]
+    fn test_map_each_is_lazy() {
+        let mut entered = Vec::new();
+        {
+            let mut m = MultiReader::builder(vec![&b"a"[..], &b"b"[..]])
+                .map_each(|i, r| {
+                    entered.push(i);
+                    r
+                })
+                .empty_read_policy(|_, _| EmptyRead::Advance)
+                .build();
+            assert_eq!(m.read(&mut [0; 4]).unwrap(), 1);
+        }
+        assert_eq!(entered, vec![0]);
+    }
+}
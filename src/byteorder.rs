@@ -0,0 +1,144 @@
+//! Reading integers and floats, in the style of the `byteorder` crate.
+//!
+//! The values are read with `read_exact`, so one that straddles the end of
+//! a source is put together from both sides of it:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use multi_reader::MultiReader;
+//! use multi_reader::byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+//!
+//! fn main() {
+//!     let segments = vec![&b"\x01\x02\x03"[..], &b"\x04\x00\x01"[..]];
+//!     let mut reader = MultiReader::new(segments.into_iter());
+//!     assert_eq!(reader.read_u32::<LittleEndian>().unwrap(), 0x0403_0201);
+//!     assert_eq!(reader.read_u16::<BigEndian>().unwrap(), 1);
+//! }
+//! ```
+
+use std::io;
+use std::io::Read;
+
+/// How the bytes of a value are ordered.
+pub trait ByteOrder {
+    fn read_u16(buf: [u8; 2]) -> u16;
+    fn read_u32(buf: [u8; 4]) -> u32;
+    fn read_u64(buf: [u8; 8]) -> u64;
+}
+
+pub enum LittleEndian {}
+
+pub enum BigEndian {}
+
+pub type LE = LittleEndian;
+pub type BE = BigEndian;
+pub type NetworkEndian = BigEndian;
+
+impl ByteOrder for LittleEndian {
+    fn read_u16(buf: [u8; 2]) -> u16 {
+        u16::from_le_bytes(buf)
+    }
+
+    fn read_u32(buf: [u8; 4]) -> u32 {
+        u32::from_le_bytes(buf)
+    }
+
+    fn read_u64(buf: [u8; 8]) -> u64 {
+        u64::from_le_bytes(buf)
+    }
+}
+
+impl ByteOrder for BigEndian {
+    fn read_u16(buf: [u8; 2]) -> u16 {
+        u16::from_be_bytes(buf)
+    }
+
+    fn read_u32(buf: [u8; 4]) -> u32 {
+        u32::from_be_bytes(buf)
+    }
+
+    fn read_u64(buf: [u8; 8]) -> u64 {
+        u64::from_be_bytes(buf)
+    }
+}
+
+/// Typed reads for any reader. A value cut short by the end of the stream
+/// fails with `UnexpectedEof`, its bytes read so far lost.
+pub trait ReadBytesExt: Read {
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_i8(&mut self) -> io::Result<i8> {
+        self.read_u8().map(|v| v as i8)
+    }
+
+    fn read_u16<T: ByteOrder>(&mut self) -> io::Result<u16> {
+        let mut buf = [0; 2];
+        self.read_exact(&mut buf)?;
+        Ok(T::read_u16(buf))
+    }
+
+    fn read_i16<T: ByteOrder>(&mut self) -> io::Result<i16> {
+        self.read_u16::<T>().map(|v| v as i16)
+    }
+
+    fn read_u32<T: ByteOrder>(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(T::read_u32(buf))
+    }
+
+    fn read_i32<T: ByteOrder>(&mut self) -> io::Result<i32> {
+        self.read_u32::<T>().map(|v| v as i32)
+    }
+
+    fn read_u64<T: ByteOrder>(&mut self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(T::read_u64(buf))
+    }
+
+    fn read_i64<T: ByteOrder>(&mut self) -> io::Result<i64> {
+        self.read_u64::<T>().map(|v| v as i64)
+    }
+
+    fn read_f32<T: ByteOrder>(&mut self) -> io::Result<f32> {
+        self.read_u32::<T>().map(f32::from_bits)
+    }
+
+    fn read_f64<T: ByteOrder>(&mut self) -> io::Result<f64> {
+        self.read_u64::<T>().map(f64::from_bits)
+    }
+}
+
+impl<R: Read + ?Sized> ReadBytesExt for R {}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::{ReadBytesExt, BE, LE};
+    use testing::ShortReader;
+    use MultiReader;
+
+    #[test]
+    fn test_values_across_boundaries() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(-2i16).to_be_bytes());
+        bytes.extend_from_slice(&0x0102_0304_0506_0708u64.to_le_bytes());
+        bytes.extend_from_slice(&1.5f64.to_be_bytes());
+        bytes.extend_from_slice(&(-7i32).to_le_bytes());
+        bytes.push(0xff);
+        // One byte per source, read one byte at a time.
+        let sources: Vec<_> = bytes.chunks(1).map(|b| ShortReader::new(b, 1)).collect();
+        let mut m = MultiReader::new(sources.into_iter());
+        assert_eq!(m.read_i16::<BE>().unwrap(), -2);
+        assert_eq!(m.read_u64::<LE>().unwrap(), 0x0102_0304_0506_0708);
+        assert_eq!(m.read_f64::<BE>().unwrap(), 1.5);
+        assert_eq!(m.read_i32::<LE>().unwrap(), -7);
+        assert_eq!(m.read_i8().unwrap(), -1);
+        assert_eq!(m.read_u8().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
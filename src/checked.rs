@@ -0,0 +1,245 @@
+//! A container of integrity-checked blobs.
+//!
+//! `Encoder` emits every source as a frame made of the payload length (a
+//! little-endian `u32`), the payload and its CRC-32 (little-endian too).
+//! `Decoder` checks every frame and reads their payloads one after another:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::checked::{Decoder, Encoder};
+//!
+//! fn main() {
+//!     let blobs = vec![&b"first "[..], &b""[..], &b"second"[..]];
+//!     let mut container = Vec::new();
+//!     Encoder::new(blobs.into_iter()).read_to_end(&mut container).unwrap();
+//!
+//!     let mut s = String::new();
+//!     Decoder::new(&container[..]).read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "first second");
+//! }
+//! ```
+//!
+//! Both sides hold one frame at a time in memory, so that no byte of a
+//! corrupt frame is passed on.
+
+use std::io;
+use std::io::Read;
+use std::mem;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC-32 of `data`, as used by zlib, gzip and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc = CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+pub struct Encoder<I> {
+    readers: I,
+    frame: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read, I: Iterator<Item = R>> Encoder<I> {
+    pub fn new(readers: I) -> Encoder<I> {
+        Encoder {
+            readers,
+            frame: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn next_frame(&mut self) -> io::Result<bool> {
+        let mut reader = match self.readers.next() {
+            Some(r) => r,
+            None => return Ok(false),
+        };
+        self.frame.clear();
+        self.frame.extend_from_slice(&[0; 4]);
+        reader.read_to_end(&mut self.frame)?;
+        let len = self.frame.len() - 4;
+        if len > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "source does not fit a frame"));
+        }
+        let crc = crc32(&self.frame[4..]);
+        self.frame[..4].copy_from_slice(&(len as u32).to_le_bytes());
+        self.frame.extend_from_slice(&crc.to_le_bytes());
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Read for Encoder<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.frame.len() && !self.next_frame()? {
+            return Ok(0);
+        }
+        let n = ::std::cmp::min(buf.len(), self.frame.len() - self.pos);
+        buf[..n].copy_from_slice(&self.frame[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+pub struct Decoder<R> {
+    inner: R,
+    index: u64,
+    payload: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R) -> Decoder<R> {
+        Decoder {
+            inner,
+            index: 0,
+            payload: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads and checks the next frame, returning its payload, or `None` if
+    /// the stream ends at a frame boundary. Whatever `read` left of the
+    /// payload before is skipped.
+    pub fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.next_frame()?;
+        if self.done {
+            return Ok(None);
+        }
+        self.pos = 0;
+        Ok(Some(mem::take(&mut self.payload)))
+    }
+
+    fn next_frame(&mut self) -> io::Result<()> {
+        let mut len = [0; 4];
+        let mut filled = 0;
+        while filled < len.len() {
+            match self.inner.read(&mut len[filled..]) {
+                Ok(0) if filled == 0 => {
+                    self.done = true;
+                    return Ok(());
+                }
+                Ok(0) => return Err(truncated()),
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let len = u32::from_le_bytes(len) as u64 + 4;
+        self.payload.clear();
+        self.pos = 0;
+        let read = (&mut self.inner).take(len).read_to_end(&mut self.payload)?;
+        if (read as u64) < len {
+            return Err(truncated());
+        }
+        let at = self.payload.len() - 4;
+        let mut crc = [0; 4];
+        crc.copy_from_slice(&self.payload[at..]);
+        self.payload.truncate(at);
+        if crc32(&self.payload) != u32::from_le_bytes(crc) {
+            self.payload.clear();
+            let msg = format!("frame {} is corrupt: CRC-32 mismatch", self.index);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        }
+        self.index += 1;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.payload.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.next_frame()?;
+        }
+        let n = ::std::cmp::min(buf.len(), self.payload.len() - self.pos);
+        buf[..n].copy_from_slice(&self.payload[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use super::{crc32, Decoder, Encoder};
+    use MultiReader;
+
+    fn encode(blobs: Vec<&[u8]>) -> Vec<u8> {
+        let mut out = Vec::new();
+        Encoder::new(blobs.into_iter()).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(encode(vec![&b"a"[..]]), b"\x01\x00\x00\x00a\x43\xbe\xb7\xe8");
+    }
+
+    #[test]
+    fn test_frames_across_sources() {
+        let data = encode(vec![&b"one"[..], &vec![7; 10_000][..], &b""[..]]);
+        // Containers split at arbitrary points, as if written in segments.
+        let mut d = Decoder::new(MultiReader::new(data.chunks(1000)));
+        assert_eq!(d.read_frame().unwrap().unwrap(), b"one");
+        assert_eq!(d.read_frame().unwrap().unwrap(), vec![7; 10_000]);
+        assert_eq!(d.read_frame().unwrap().unwrap(), b"");
+        assert_eq!(d.read_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn test_corruption_and_truncation() {
+        let mut data = encode(vec![&b"good"[..], &b"bad"[..]]);
+        let n = data.len();
+        data[n - 5] ^= 1;
+        let mut out = Vec::new();
+        let err = Decoder::new(&data[..]).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "frame 1 is corrupt: CRC-32 mismatch");
+        assert_eq!(out, b"good");
+
+        let data = encode(vec![&b"cut"[..]]);
+        let err = Decoder::new(&data[..data.len() - 1]).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
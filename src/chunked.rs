@@ -0,0 +1,126 @@
+//! HTTP/1.1 chunked transfer-encoding.
+//!
+//! `Encoder` wraps any reader, typically a whole chain, and emits its data
+//! as chunks followed by the terminal zero-size chunk:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::{chunked, MultiReader};
+//!
+//! fn main() {
+//!     let parts = vec![&b"hello "[..], &b"world"[..]];
+//!     let mut body = chunked::Encoder::new(MultiReader::new(parts.into_iter()));
+//!     let mut s = String::new();
+//!     body.read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n");
+//! }
+//! ```
+
+use std::io;
+use std::io::Read;
+
+const CHUNK_SIZE: usize = 8192;
+
+pub struct Encoder<R> {
+    inner: R,
+    data: Box<[u8]>,
+    chunk: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Encoder<R> {
+    pub fn new(inner: R) -> Encoder<R> {
+        Encoder::with_chunk_size(inner, CHUNK_SIZE)
+    }
+
+    /// Creates an encoder emitting chunks of at most `chunk_size` bytes.
+    pub fn with_chunk_size(inner: R, chunk_size: usize) -> Encoder<R> {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        Encoder {
+            inner,
+            data: vec![0; chunk_size].into_boxed_slice(),
+            chunk: Vec::with_capacity(chunk_size + 32),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let n = loop {
+            match self.inner.read(&mut self.data) {
+                Ok(n) => break n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        };
+        self.chunk.clear();
+        self.pos = 0;
+        self.chunk.extend_from_slice(format!("{:x}\r\n", n).as_bytes());
+        self.chunk.extend_from_slice(&self.data[..n]);
+        self.chunk.extend_from_slice(b"\r\n");
+        if n == 0 {
+            self.done = true;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Encoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let n = ::std::cmp::min(buf.len(), self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use super::Encoder;
+
+    fn encode<R: Read>(mut e: Encoder<R>) -> String {
+        let mut s = String::new();
+        e.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_empty_body() {
+        assert_eq!(encode(Encoder::new(io::empty())), "0\r\n\r\n");
+    }
+
+    #[test]
+    fn test_chunk_size() {
+        let e = Encoder::with_chunk_size(&b"abcdefghijklmnopq"[..], 16);
+        assert_eq!(encode(e), "10\r\nabcdefghijklmnop\r\n1\r\nq\r\n0\r\n\r\n");
+    }
+
+    #[test]
+    fn test_small_reads() {
+        let mut e = Encoder::new(&b"ab"[..]);
+        let mut out = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            let n = e.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(out, b"2\r\nab\r\n0\r\n\r\n");
+    }
+}
@@ -0,0 +1,141 @@
+//! Dropping empty sources, see `MultiReader::skip_empty`.
+//!
+//! An empty source is only known to be empty once read from, so every
+//! source is read from as it is pulled from the iterator, and yielded only
+//! if that read returned some bytes. An empty source thus never becomes the
+//! current one: it gets no index, no separator and no `map_each` call.
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//!
+//! fn main() {
+//!     let mut reader = MultiReader::new(vec![&b"a"[..], &b""[..], &b"b"[..]].into_iter())
+//!         .skip_empty()
+//!         .separator(b", ");
+//!     let mut s = String::new();
+//!     reader.read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "a, b");
+//! }
+//! ```
+
+use std::io;
+use std::io::Read;
+
+const PROBE_SIZE: usize = 512;
+
+/// A source along with the bytes read from it to tell that it is not empty.
+pub struct Probed<R> {
+    head: [u8; PROBE_SIZE],
+    pos: usize,
+    len: usize,
+    error: Option<io::Error>,
+    inner: R,
+}
+
+impl<R: Read> Probed<R> {
+    /// Reads from `inner`, returning `None` if it turns out to be empty. A
+    /// failed read yields the source, to fail its first read in turn.
+    pub fn probe(mut inner: R) -> Option<Probed<R>> {
+        let mut head = [0; PROBE_SIZE];
+        let (len, error) = loop {
+            match inner.read(&mut head) {
+                Ok(0) => return None,
+                Ok(n) => break (n, None),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break (0, Some(e)),
+            }
+        };
+        Some(Probed {
+            head,
+            pos: 0,
+            len,
+            error,
+            inner,
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Probed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if self.pos < self.len {
+            let n = ::std::cmp::min(buf.len(), self.len - self.pos);
+            buf[..n].copy_from_slice(&self.head[self.pos..self.pos + n]);
+            self.pos += n;
+            return Ok(n);
+        }
+        self.inner.read(buf)
+    }
+}
+
+/// Source iterator behind `MultiReader::skip_empty`.
+pub struct SkipEmpty<I> {
+    readers: I,
+}
+
+impl<I> SkipEmpty<I> {
+    pub fn new(readers: I) -> SkipEmpty<I> {
+        SkipEmpty { readers }
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Iterator for SkipEmpty<I> {
+    type Item = Probed<R>;
+
+    fn next(&mut self) -> Option<Probed<R>> {
+        loop {
+            if let Some(probed) = Probed::probe(self.readers.next()?) {
+                return Some(probed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use testing::MaybeErrReader;
+    use MultiReader;
+
+    #[test]
+    fn test_skip_empty() {
+        let mut entered = Vec::new();
+        {
+            let sources = vec![&b""[..], &b"ab"[..], &b""[..], &b""[..], &b"c"[..], &b""[..]];
+            let mut m = MultiReader::new(sources.into_iter())
+                .skip_empty()
+                .map_each(|i, r| {
+                    entered.push(i);
+                    r
+                });
+            let mut buf = [0; 4];
+            assert_eq!(m.read(&mut buf).unwrap(), 2);
+            assert_eq!(m.read(&mut buf).unwrap(), 1);
+            assert_eq!(m.source_index(), 1);
+            assert_eq!(m.read(&mut buf).unwrap(), 0);
+        }
+        assert_eq!(entered, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_probe_error() {
+        let sources = vec![MaybeErrReader::broken(&b"ab"[..], 0), MaybeErrReader::good(&b""[..])];
+        let mut m = MultiReader::new(sources.into_iter()).skip_empty();
+        let mut out = Vec::new();
+        assert!(m.read_to_end(&mut out).is_err());
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"ab");
+    }
+}
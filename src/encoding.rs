@@ -0,0 +1,437 @@
+//! Transcoding of legacy text encodings to UTF-8.
+//!
+//! Wrap every source in a `Decoder` for its declared encoding (or let it
+//! sniff a BOM) and the chain yields UTF-8 only:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//! use multi_reader::encoding::{Decoder, Encoding};
+//!
+//! fn main() {
+//!     let sources = vec![
+//!         (&b"caf\xe9 "[..], Encoding::Windows1252),
+//!         (&b"\xff\xfet\x00e\x00a\x00"[..], Encoding::Utf8),
+//!     ];
+//!     let mut reader = MultiReader::new(sources.into_iter().map(|(r, e)| Decoder::detect(r, e)));
+//!     let mut s = String::new();
+//!     reader.read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "café tea");
+//! }
+//! ```
+//!
+//! Malformed input is replaced with U+FFFD rather than reported as an error.
+//! Only a handful of common encodings are built in.
+//!
+//! Sources that are already in the right encoding but start with a BOM can be
+//! passed through `strip_boms` instead, so no `\u{FEFF}` ends up mid-stream.
+
+use std::io;
+use std::io::Read;
+use std::str;
+
+const INPUT_SIZE: usize = 4096;
+
+const WINDOWS_1252: [u16; 32] = [
+    0x20AC, 0x0081, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021,
+    0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0x008D, 0x017D, 0x008F,
+    0x0090, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014,
+    0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x009D, 0x017E, 0x0178,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+    Latin1,
+}
+
+impl Encoding {
+    /// Looks an encoding up by one of its common labels, ignoring case.
+    pub fn for_label(label: &str) -> Option<Encoding> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" | "utf-16" => Some(Encoding::Utf16Le),
+            "utf-16be" => Some(Encoding::Utf16Be),
+            "windows-1252" | "cp1252" => Some(Encoding::Windows1252),
+            "iso-8859-1" | "latin1" | "latin-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    fn sniff(bytes: &[u8]) -> Option<(Encoding, usize)> {
+        if bytes.starts_with(b"\xef\xbb\xbf") {
+            Some((Encoding::Utf8, 3))
+        } else if bytes.starts_with(b"\xff\xfe") {
+            Some((Encoding::Utf16Le, 2))
+        } else if bytes.starts_with(b"\xfe\xff") {
+            Some((Encoding::Utf16Be, 2))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Decoder<R> {
+    inner: R,
+    encoding: Encoding,
+    detect: bool,
+    eof: bool,
+    carry: Vec<u8>,
+    surrogate: Option<u16>,
+    output: Vec<u8>,
+    out_pos: usize,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Decodes `inner` as `encoding`.
+    pub fn new(inner: R, encoding: Encoding) -> Decoder<R> {
+        Decoder {
+            inner,
+            encoding,
+            detect: false,
+            eof: false,
+            carry: Vec::new(),
+            surrogate: None,
+            output: Vec::new(),
+            out_pos: 0,
+        }
+    }
+
+    /// Decodes `inner` according to its BOM, which is removed, falling back
+    /// to `encoding` if there is none.
+    pub fn detect(inner: R, encoding: Encoding) -> Decoder<R> {
+        let mut d = Decoder::new(inner, encoding);
+        d.detect = true;
+        d
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        self.output.clear();
+        self.out_pos = 0;
+        let mut buf = [0; INPUT_SIZE];
+        while self.output.is_empty() && !self.eof {
+            let n = self.inner.read(&mut buf)?;
+            let mut data = ::std::mem::take(&mut self.carry);
+            data.extend_from_slice(&buf[..n]);
+            self.eof = n == 0;
+
+            let mut start = 0;
+            if self.detect {
+                if data.len() < 3 && !self.eof {
+                    self.carry = data;
+                    continue;
+                }
+                if let Some((encoding, bom)) = Encoding::sniff(&data) {
+                    self.encoding = encoding;
+                    start = bom;
+                }
+                self.detect = false;
+            }
+            self.decode(&data[start..]);
+            if self.eof && (!self.carry.is_empty() || self.surrogate.is_some()) {
+                self.carry.clear();
+                self.surrogate = None;
+                self.push('\u{FFFD}');
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(&mut self, data: &[u8]) {
+        match self.encoding {
+            Encoding::Utf8 => {
+                let mut rest = data;
+                while let Err(e) = str::from_utf8(rest) {
+                    let valid = e.valid_up_to();
+                    self.output.extend_from_slice(&rest[..valid]);
+                    match e.error_len() {
+                        Some(len) => {
+                            self.push('\u{FFFD}');
+                            rest = &rest[valid + len..];
+                        }
+                        // The sequence may end in the next read.
+                        None => {
+                            self.carry.extend_from_slice(&rest[valid..]);
+                            return;
+                        }
+                    }
+                }
+                self.output.extend_from_slice(rest);
+            }
+            Encoding::Latin1 => {
+                for &b in data {
+                    self.push(b as char);
+                }
+            }
+            Encoding::Windows1252 => {
+                for &b in data {
+                    let c = match b {
+                        0x80..=0x9f => WINDOWS_1252[(b - 0x80) as usize] as u32,
+                        _ => b as u32,
+                    };
+                    self.push(::std::char::from_u32(c).unwrap_or('\u{FFFD}'));
+                }
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                for pair in data.chunks(2) {
+                    if pair.len() < 2 {
+                        self.carry.push(pair[0]);
+                        break;
+                    }
+                    let unit = if self.encoding == Encoding::Utf16Le {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    };
+                    self.push_utf16(unit);
+                }
+            }
+        }
+    }
+
+    fn push_utf16(&mut self, unit: u16) {
+        if let Some(high) = self.surrogate.take() {
+            if (0xDC00..0xE000).contains(&unit) {
+                let c = 0x10000 + (((high as u32) - 0xD800) << 10) + ((unit as u32) - 0xDC00);
+                self.push(::std::char::from_u32(c).unwrap_or('\u{FFFD}'));
+                return;
+            }
+            self.push('\u{FFFD}');
+        }
+        match unit {
+            0xD800..=0xDBFF => self.surrogate = Some(unit),
+            0xDC00..=0xDFFF => self.push('\u{FFFD}'),
+            _ => self.push(::std::char::from_u32(unit as u32).unwrap_or('\u{FFFD}')),
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        let mut utf8 = [0; 4];
+        self.output.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+    }
+}
+
+/// Removes a leading UTF-8 or UTF-16 BOM from a reader.
+pub struct BomStripper<R> {
+    inner: R,
+    head: [u8; 3],
+    head_pos: usize,
+    head_len: usize,
+    checked: bool,
+}
+
+impl<R: Read> BomStripper<R> {
+    pub fn new(inner: R) -> BomStripper<R> {
+        BomStripper {
+            inner,
+            head: [0; 3],
+            head_pos: 0,
+            head_len: 0,
+            checked: false,
+        }
+    }
+
+    fn passthrough(inner: R) -> BomStripper<R> {
+        let mut s = BomStripper::new(inner);
+        s.checked = true;
+        s
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn check(&mut self) -> io::Result<()> {
+        while self.head_len < self.head.len() {
+            match self.inner.read(&mut self.head[self.head_len..]) {
+                Ok(0) => break,
+                Ok(n) => self.head_len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some((_, bom)) = Encoding::sniff(&self.head[..self.head_len]) {
+            self.head_pos = bom;
+        }
+        self.checked = true;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BomStripper<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.checked {
+            self.check()?;
+        }
+        if self.head_pos < self.head_len {
+            let n = ::std::cmp::min(buf.len(), self.head_len - self.head_pos);
+            buf[..n].copy_from_slice(&self.head[self.head_pos..self.head_pos + n]);
+            self.head_pos += n;
+            return Ok(n);
+        }
+        self.inner.read(buf)
+    }
+}
+
+/// Iterator returned by `strip_boms`.
+pub struct StripBoms<I> {
+    sources: I,
+    keep_first: bool,
+}
+
+impl<R: Read, I: Iterator<Item = R>> Iterator for StripBoms<I> {
+    type Item = BomStripper<R>;
+
+    fn next(&mut self) -> Option<BomStripper<R>> {
+        let source = self.sources.next()?;
+        if self.keep_first {
+            self.keep_first = false;
+            Some(BomStripper::passthrough(source))
+        } else {
+            Some(BomStripper::new(source))
+        }
+    }
+}
+
+/// Wraps every source so that its BOM is dropped, except for the very first
+/// one if `keep_first` is set.
+pub fn strip_boms<I: IntoIterator>(sources: I, keep_first: bool) -> StripBoms<I::IntoIter> {
+    StripBoms {
+        sources: sources.into_iter(),
+        keep_first,
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.out_pos == self.output.len() {
+            self.fill()?;
+        }
+        let n = ::std::cmp::min(buf.len(), self.output.len() - self.out_pos);
+        buf[..n].copy_from_slice(&self.output[self.out_pos..self.out_pos + n]);
+        self.out_pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use super::{strip_boms, BomStripper, Decoder, Encoding};
+    use MultiReader;
+
+    fn decode<R: Read>(mut r: R) -> String {
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_single_byte_encodings() {
+        let src = &b"\x80 na\xefve \x9c"[..];
+        assert_eq!(decode(Decoder::new(src, Encoding::Windows1252)), "€ naïve œ");
+        assert_eq!(decode(Decoder::new(src, Encoding::Latin1)),
+                   "\u{80} naïve \u{9c}");
+    }
+
+    #[test]
+    fn test_utf16() {
+        let le = &b"h\x00i\x00=\xd8\x00\xde"[..];
+        assert_eq!(decode(Decoder::new(le, Encoding::Utf16Le)), "hi😀");
+        let be = &b"\x00h\x00i"[..];
+        assert_eq!(decode(Decoder::new(be, Encoding::Utf16Be)), "hi");
+    }
+
+    #[test]
+    fn test_utf16_malformed() {
+        let lone_low = &b"\x00\xdch\x00"[..];
+        assert_eq!(decode(Decoder::new(lone_low, Encoding::Utf16Le)), "\u{FFFD}h");
+        let unpaired_high = &b"\x00\xd8h\x00"[..];
+        assert_eq!(decode(Decoder::new(unpaired_high, Encoding::Utf16Le)), "\u{FFFD}h");
+        let odd = &b"h\x00i"[..];
+        assert_eq!(decode(Decoder::new(odd, Encoding::Utf16Le)), "h\u{FFFD}");
+    }
+
+    #[test]
+    fn test_utf8_malformed() {
+        let src = &b"a\xffb\xe2\x82c\xc3"[..];
+        assert_eq!(decode(Decoder::new(src, Encoding::Utf8)), "a\u{FFFD}b\u{FFFD}c\u{FFFD}");
+        let sources = vec![&b"\xe2"[..], &b"\x82"[..], &b"\xac"[..]];
+        let r = MultiReader::new(sources.into_iter());
+        assert_eq!(decode(Decoder::new(r, Encoding::Utf8)), "€");
+    }
+
+    #[test]
+    fn test_utf16_split_across_reads() {
+        let sources = vec![&b"h"[..], &b"\x00=\xd8"[..], &b"\x00"[..], &b"\xde"[..]];
+        let r = MultiReader::new(sources.into_iter());
+        assert_eq!(decode(Decoder::new(r, Encoding::Utf16Le)), "h😀");
+    }
+
+    #[test]
+    fn test_bom_detection() {
+        let utf8 = &b"\xef\xbb\xbfok"[..];
+        let d = Decoder::detect(utf8, Encoding::Latin1);
+        assert_eq!(decode(d), "ok");
+        let be = &b"\xfe\xff\x00o\x00k"[..];
+        assert_eq!(decode(Decoder::detect(be, Encoding::Utf8)), "ok");
+        let none = &b"\xe9"[..];
+        assert_eq!(decode(Decoder::detect(none, Encoding::Latin1)), "é");
+        assert_eq!(decode(Decoder::detect(&b""[..], Encoding::Latin1)), "");
+    }
+
+    #[test]
+    fn test_mixed_chain() {
+        let sources = vec![
+            (&b"\xff\xfea\x00"[..], Encoding::Windows1252),
+            (&b"\x93b\x94"[..], Encoding::Windows1252),
+            (&b"c"[..], Encoding::Utf8),
+        ];
+        let m = MultiReader::new(sources.into_iter().map(|(r, e)| Decoder::detect(r, e)));
+        assert_eq!(decode(m), "a“b”c");
+    }
+
+    #[test]
+    fn test_bom_stripper() {
+        assert_eq!(decode(BomStripper::new(&b"\xef\xbb\xbfabc"[..])), "abc");
+        let mut partial = Vec::new();
+        BomStripper::new(&b"\xef\xbb"[..]).read_to_end(&mut partial).unwrap();
+        assert_eq!(partial, b"\xef\xbb");
+        assert_eq!(decode(BomStripper::new(&b"ab"[..])), "ab");
+        assert_eq!(decode(BomStripper::new(&b""[..])), "");
+
+        let split = MultiReader::new(vec![&b"\xef"[..], &b"\xbb\xbfx"[..]].into_iter());
+        let mut s = BomStripper::new(split);
+        let mut buf = [0; 8];
+        assert_eq!(s.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'x');
+    }
+
+    #[test]
+    fn test_strip_boms() {
+        let sources = vec![&b"\xef\xbb\xbfa"[..], &b"\xef\xbb\xbfb"[..], &b"c"[..]];
+        let m = MultiReader::new(strip_boms(sources.clone(), false));
+        assert_eq!(decode(m), "abc");
+        let m = MultiReader::new(strip_boms(sources, true));
+        assert_eq!(decode(m), "\u{FEFF}abc");
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(Encoding::for_label(" UTF-16LE "), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::for_label("cp1252"), Some(Encoding::Windows1252));
+        assert_eq!(Encoding::for_label("koi8-r"), None);
+    }
+}
@@ -0,0 +1,233 @@
+//! C API over a chain of callback-based sources.
+//!
+//! The caller hands in a `next` callback that fills in a `Source` and returns
+//! non-zero while there are sources left. Each source is a context pointer
+//! plus a `read` callback returning the number of bytes read, `0` at EOF or a
+//! negative value on error, and an optional `close` callback invoked once the
+//! chain is done with it.
+//!
+//! ```c
+//! struct multi_reader *r = multi_reader_new(next_file, &state);
+//! ptrdiff_t n;
+//! while ((n = multi_reader_read(r, buf, sizeof(buf))) > 0) {
+//!     consume(buf, n);
+//! }
+//! multi_reader_free(r);
+//! ```
+//!
+//! The declarations are in `include/multi_reader.h`. The crate builds as an
+//! rlib only, so a library to link against is built with, for example:
+//!
+//! ```text
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! cargo rustc --release --features ffi --crate-type staticlib
+//! ```
+
+use std::io;
+use std::io::Read;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+use MultiReader;
+
+pub type ReadFn = extern "C" fn(ctx: *mut c_void, buf: *mut u8, len: usize) -> isize;
+pub type CloseFn = extern "C" fn(ctx: *mut c_void);
+pub type NextFn = extern "C" fn(ctx: *mut c_void, source: *mut Source) -> c_int;
+
+#[repr(C)]
+pub struct Source {
+    pub ctx: *mut c_void,
+    pub read: Option<ReadFn>,
+    pub close: Option<CloseFn>,
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = match self.read {
+            Some(read) => read,
+            None => return Ok(0),
+        };
+        let n = read(self.ctx, buf.as_mut_ptr(), buf.len());
+        if n < 0 {
+            return Err(io::Error::other(format!("source read failed with {}", n)));
+        }
+        Ok(::std::cmp::min(n as usize, buf.len()))
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        if let Some(close) = self.close {
+            close(self.ctx);
+        }
+    }
+}
+
+/// Iterator asking the `next` callback for sources.
+pub struct Sources {
+    next: NextFn,
+    ctx: *mut c_void,
+}
+
+impl Iterator for Sources {
+    type Item = Source;
+
+    fn next(&mut self) -> Option<Source> {
+        let mut source = Source {
+            ctx: ptr::null_mut(),
+            read: None,
+            close: None,
+        };
+        if (self.next)(self.ctx, &mut source) == 0 {
+            source.close = None;
+            return None;
+        }
+        Some(source)
+    }
+}
+
+/// The chain behind a `struct multi_reader *`.
+pub type Handle = MultiReader<Source, Sources>;
+
+/// Creates a chain over the sources produced by `next`.
+///
+/// # Safety
+///
+/// `next` must be safe to call with `ctx` until the chain is freed.
+#[no_mangle]
+pub unsafe extern "C" fn multi_reader_new(next: NextFn, ctx: *mut c_void) -> *mut Handle {
+    Box::into_raw(Box::new(MultiReader::new(Sources { next, ctx })))
+}
+
+/// Reads up to `len` bytes into `buf`. Returns the number of bytes read,
+/// `0` at the end of the chain or `-1` on error.
+///
+/// # Safety
+///
+/// `reader` must come from `multi_reader_new` and `buf` must be valid for
+/// writes of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn multi_reader_read(reader: *mut Handle, buf: *mut u8, len: usize) -> isize {
+    if reader.is_null() || (buf.is_null() && len > 0) {
+        return -1;
+    }
+    if len == 0 {
+        return 0;
+    }
+    let buf = ::std::slice::from_raw_parts_mut(buf, len);
+    loop {
+        match (*reader).read(buf) {
+            Ok(n) => return n as isize,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => return -1,
+        }
+    }
+}
+
+/// Index of the source the last read was served from, or `SIZE_MAX` if
+/// `reader` is null.
+///
+/// # Safety
+///
+/// `reader` must come from `multi_reader_new`.
+#[no_mangle]
+pub unsafe extern "C" fn multi_reader_source_index(reader: *const Handle) -> usize {
+    if reader.is_null() {
+        return usize::MAX;
+    }
+    (*reader).source_index()
+}
+
+/// Frees the chain, closing the current source.
+///
+/// # Safety
+///
+/// `reader` must come from `multi_reader_new` and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn multi_reader_free(reader: *mut Handle) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::raw::{c_int, c_void};
+    use super::*;
+
+    struct State {
+        sources: Vec<&'static [u8]>,
+        next: usize,
+        closed: usize,
+    }
+
+    struct Cursor {
+        data: &'static [u8],
+        state: *mut State,
+    }
+
+    extern "C" fn read(ctx: *mut c_void, buf: *mut u8, len: usize) -> isize {
+        let cursor = unsafe { &mut *(ctx as *mut Cursor) };
+        if cursor.data == b"!" {
+            return -5;
+        }
+        let n = ::std::cmp::min(len, cursor.data.len());
+        unsafe { ptr::copy_nonoverlapping(cursor.data.as_ptr(), buf, n) };
+        cursor.data = &cursor.data[n..];
+        n as isize
+    }
+
+    extern "C" fn close(ctx: *mut c_void) {
+        let cursor = unsafe { Box::from_raw(ctx as *mut Cursor) };
+        unsafe { (*cursor.state).closed += 1 };
+    }
+
+    extern "C" fn next(ctx: *mut c_void, source: *mut Source) -> c_int {
+        let state = unsafe { &mut *(ctx as *mut State) };
+        if state.next == state.sources.len() {
+            return 0;
+        }
+        let cursor = Box::new(Cursor {
+            data: state.sources[state.next],
+            state,
+        });
+        state.next += 1;
+        unsafe {
+            (*source).ctx = Box::into_raw(cursor) as *mut c_void;
+            (*source).read = Some(read);
+            (*source).close = Some(close);
+        }
+        1
+    }
+
+    #[test]
+    fn test_c_api() {
+        let mut state = State {
+            sources: vec![b"abc", b"", b"de", b"!"],
+            next: 0,
+            closed: 0,
+        };
+        let mut buf = [0u8; 8];
+        unsafe {
+            let r = multi_reader_new(next, &mut state as *mut State as *mut c_void);
+            assert_eq!(multi_reader_read(r, buf.as_mut_ptr(), buf.len()), 3);
+            assert_eq!(multi_reader_read(r, buf.as_mut_ptr(), buf.len()), 2);
+            assert_eq!(&buf[..2], b"de");
+            assert_eq!(multi_reader_source_index(r), 2);
+            assert_eq!(multi_reader_read(r, buf.as_mut_ptr(), buf.len()), -1);
+            multi_reader_free(r);
+        }
+        assert_eq!(state.next, 4);
+        assert_eq!(state.closed, 4);
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        unsafe {
+            assert_eq!(multi_reader_read(ptr::null_mut(), ptr::null_mut(), 4), -1);
+            assert_eq!(multi_reader_source_index(ptr::null()), usize::MAX);
+            multi_reader_free(ptr::null_mut());
+        }
+    }
+}
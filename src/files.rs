@@ -0,0 +1,997 @@
+//! Chains of files given by path.
+//!
+//! `from_paths` collects the paths and options of a chain of files, then
+//! `into_reader` chains them, opening each one only when the chain reaches
+//! it:
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::files;
+//!
+//! fn main() {
+//!     let mut reader = files::from_paths(vec!["a.log", "b.log"]).into_reader();
+//!     let mut s = String::new();
+//!     reader.read_to_string(&mut s).unwrap();
+//! }
+//! ```
+//!
+//! `from_dir` starts from the files in a directory tree instead, in natural
+//! order so that `part2` comes before `part10`, and `sort_by_name`,
+//! `sort_by_mtime` or `sort_by` reorder them:
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use multi_reader::files::{self, Symlinks};
+//!
+//! fn main() {
+//!     let files = files::from_dir("logs").symlinks(Symlinks::Skip).files().unwrap();
+//!     let reader = files.sort_by_mtime().unwrap().into_reader();
+//! }
+//! ```
+//!
+//! A file that cannot be opened makes `read` fail once it is reached, with
+//! the path in the error message. Every later `read` fails the same way,
+//! see `Fallible`; `on_missing` skips files that do not exist instead.
+//!
+//! `into_seekable` instead stats every file upfront, still opening them
+//! lazily, so that the total length is known, for progress reporting, and
+//! the chain can seek:
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use std::io::{Read, Seek, SeekFrom};
+//! use multi_reader::files;
+//!
+//! fn main() {
+//!     let mut reader = files::from_paths(vec!["a.log", "b.log"]).into_seekable().unwrap();
+//!     let total = reader.len();
+//!     reader.seek(SeekFrom::Start(total / 2)).unwrap();
+//!     let mut rest = Vec::new();
+//!     reader.read_to_end(&mut rest).unwrap();
+//!     println!("{}%", 100 * reader.position() / total);
+//! }
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use std::vec;
+
+use flatten::Sources;
+use seekable::SeekableMultiReader;
+use {Fallible, MultiReader};
+
+type Filter = Box<dyn Fn(&fs::Metadata) -> bool + Send>;
+type OnMissing = Box<dyn FnMut(&Path) + Send>;
+
+#[cfg(unix)]
+type Identity = (u64, u64);
+#[cfg(not(unix))]
+type Identity = PathBuf;
+
+/// Paths and options of a chain of files, see `from_paths`.
+pub struct Files {
+    paths: Vec<PathBuf>,
+    select: Select,
+    opener: Opener,
+    on_missing: Option<OnMissing>,
+    detect_changes: bool,
+}
+
+/// Starts configuring a chain of the files at `paths`, in that order.
+pub fn from_paths<P, T>(paths: T) -> Files
+    where P: Into<PathBuf>,
+          T: IntoIterator<Item = P>
+{
+    Files {
+        paths: paths.into_iter().map(Into::into).collect(),
+        select: Select {
+            filters: Vec::new(),
+            seen: None,
+        },
+        opener: Opener {
+            attempts: 1,
+            initial_delay: Duration::from_millis(0),
+            max_delay: Duration::from_millis(0),
+            lock: false,
+        },
+        on_missing: None,
+        detect_changes: false,
+    }
+}
+
+/// Starts listing the files in the tree under `dir`, see `Dir::files`.
+pub fn from_dir<P: Into<PathBuf>>(dir: P) -> Dir {
+    Dir {
+        root: dir.into(),
+        symlinks: Symlinks::Follow,
+        max_depth: usize::MAX,
+        hidden: true,
+    }
+}
+
+/// What to do with symbolic links met while listing a tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Lists the files links point to and walks the directories they point
+    /// to, unless that is one the walk is already in, which would loop.
+    Follow,
+    /// Leaves links out.
+    Skip,
+    /// Fails the listing.
+    Error,
+}
+
+/// A directory tree to list the files of, see `from_dir`.
+pub struct Dir {
+    root: PathBuf,
+    symlinks: Symlinks,
+    max_depth: usize,
+    hidden: bool,
+}
+
+impl Dir {
+    /// Sets what to do with symbolic links, `Symlinks::Follow` by default.
+    pub fn symlinks(mut self, symlinks: Symlinks) -> Dir {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Lists files at most `depth` levels down the tree: 1 for only those
+    /// directly in it. There is no limit by default.
+    pub fn max_depth(mut self, depth: usize) -> Dir {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Sets whether to list files and walk directories whose names start
+    /// with a dot, which it does by default.
+    pub fn include_hidden(mut self, hidden: bool) -> Dir {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Walks the tree now and starts configuring a chain of the files in
+    /// it, in natural order of their paths, see `Files::sort_natural`.
+    pub fn files(self) -> io::Result<Files> {
+        let root = fs::metadata(&self.root).map_err(|e| with_path(e, &self.root))?;
+        let mut ancestors = vec![identity(&self.root, &root)?];
+        let mut paths = Vec::new();
+        self.walk(&self.root, 1, &mut ancestors, &mut paths)?;
+        Ok(from_paths(paths).sort_natural())
+    }
+
+    fn walk(&self,
+            dir: &Path,
+            depth: usize,
+            ancestors: &mut Vec<Identity>,
+            paths: &mut Vec<PathBuf>)
+            -> io::Result<()>
+    {
+        if depth > self.max_depth {
+            return Ok(());
+        }
+        for entry in fs::read_dir(dir).map_err(|e| with_path(e, dir))? {
+            let entry = entry.map_err(|e| with_path(e, dir))?;
+            if !self.hidden && entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            let is_link = entry.file_type().map_err(|e| with_path(e, &path))?.is_symlink();
+            if is_link {
+                match self.symlinks {
+                    Symlinks::Follow => {}
+                    Symlinks::Skip => continue,
+                    Symlinks::Error => {
+                        let msg = format!("{}: symbolic link in the tree", path.display());
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+                    }
+                }
+            }
+            let metadata = fs::metadata(&path).map_err(|e| with_path(e, &path))?;
+            if !metadata.is_dir() {
+                paths.push(path);
+                continue;
+            }
+            let id = identity(&path, &metadata)?;
+            if ancestors.contains(&id) {
+                continue;
+            }
+            ancestors.push(id);
+            self.walk(&path, depth + 1, ancestors, paths)?;
+            ancestors.pop();
+        }
+        Ok(())
+    }
+}
+
+impl Files {
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Sorts the files by path.
+    pub fn sort_by_name(self) -> Files {
+        self.sort_by(|a, b| a.cmp(b))
+    }
+
+    /// Sorts the files by path, comparing runs of digits by their numeric
+    /// value, so that `part2` comes before `part10` and `file.9.gz` before
+    /// `file.10.gz`.
+    pub fn sort_natural(self) -> Files {
+        self.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()))
+    }
+
+    /// Stats every file now to sort them by modification time, oldest
+    /// first, such as rotated logs in chronological order. Files modified
+    /// at the same time keep their order.
+    pub fn sort_by_mtime(mut self) -> io::Result<Files> {
+        let mut keyed = self.paths
+            .into_iter()
+            .map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified());
+                modified.map(|t| (t, path.clone())).map_err(|e| with_path(e, &path))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        keyed.sort_by_key(|&(t, _)| t);
+        self.paths = keyed.into_iter().map(|(_, path)| path).collect();
+        Ok(self)
+    }
+
+    /// Sorts the files with `compare`, keeping the order of equal ones.
+    pub fn sort_by<F>(mut self, compare: F) -> Files
+        where F: FnMut(&PathBuf, &PathBuf) -> Ordering
+    {
+        self.paths.sort_by(compare);
+        self
+    }
+
+    /// Leaves out regular files that are empty when the chain reaches them,
+    /// as told by their metadata, without reading from them. Unlike
+    /// `MultiReader::skip_empty`, this is no use for files such as those in
+    /// `/proc` that report a length of 0 regardless of their contents.
+    pub fn skip_empty(self) -> Files {
+        self.filter(|m| !(m.is_file() && m.len() == 0))
+    }
+
+    /// Leaves out files whose metadata `predicate` returns `false` for,
+    /// checked when the chain reaches them. Several filters all have to
+    /// pass.
+    pub fn filter<F>(mut self, predicate: F) -> Files
+        where F: Fn(&fs::Metadata) -> bool + Send + 'static
+    {
+        self.select.filters.push(Box::new(predicate));
+        self
+    }
+
+    /// Leaves out files already in the chain under another path, or the
+    /// same path given twice. Files are told apart by device and inode on
+    /// Unix, by canonical path elsewhere.
+    pub fn dedupe(mut self) -> Files {
+        self.select.seen = Some(HashSet::new());
+        self
+    }
+
+    /// Makes up to `attempts` attempts at opening each file while opening
+    /// fails for a reason that may go away, such as running out of file
+    /// descriptors or a network file system timing out. Files are opened
+    /// once by default.
+    pub fn open_attempts(mut self, attempts: usize) -> Files {
+        assert!(attempts > 0, "at least one attempt is needed");
+        self.opener.attempts = attempts;
+        self
+    }
+
+    /// Waits `initial` before retrying to open a file, doubling the delay
+    /// after every failed retry up to `max`. No delay by default.
+    pub fn open_backoff(mut self, initial: Duration, max: Duration) -> Files {
+        self.opener.initial_delay = initial;
+        self.opener.max_delay = max;
+        self
+    }
+
+    /// Takes a shared advisory lock on each file once opened, waiting for
+    /// any exclusive lock to be released first, so that writers that honor
+    /// locks do not truncate a file while it is being read. The lock is
+    /// released when the chain moves on to the next file.
+    pub fn lock_shared(mut self) -> Files {
+        self.opener.lock = true;
+        self
+    }
+
+    /// Leaves out files that do not exist when the chain reaches them, such
+    /// as logs rotated away since they were listed, calling `warn` with the
+    /// path of each instead of failing `read`. A seekable chain checks when
+    /// stating the files: once its length is known, a file that disappears
+    /// still fails `read`.
+    pub fn skip_missing<F>(mut self, warn: F) -> Files
+        where F: FnMut(&Path) + Send + 'static
+    {
+        self.on_missing = Some(Box::new(warn));
+        self
+    }
+
+    /// Makes `read` fail at the end of a regular file whose length or
+    /// modification time changed since it was opened, rather than silently
+    /// chaining a file truncated or rewritten mid-read. The chain moves on
+    /// to the next file on the following `read`. Seekable chains do not
+    /// need this to tell that a file shrank.
+    pub fn detect_changes(mut self) -> Files {
+        self.detect_changes = true;
+        self
+    }
+
+    /// Chains the files, opening each one when the chain reaches it.
+    pub fn into_reader(self) -> MultiReader<Fallible<Source>, Open> {
+        MultiReader::new(Open {
+            paths: self.paths.into_iter(),
+            select: self.select,
+            opener: self.opener,
+            on_missing: self.on_missing,
+            detect_changes: self.detect_changes,
+        })
+    }
+
+    /// Opens every file now, so that files deleted or rotated away once the
+    /// chain is made are still read in full: an open file keeps its data
+    /// on Unix. The cost is one file descriptor per file, held until the
+    /// chain moves past it, which is why this fails without opening any if
+    /// there are more than `max_open` files.
+    pub fn into_snapshot(self, max_open: usize)
+        -> io::Result<MultiReader<Source, vec::IntoIter<Source>>>
+    {
+        if self.paths.len() > max_open {
+            let msg = format!("{} files to snapshot, more than the {} allowed open",
+                              self.paths.len(),
+                              max_open);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let mut open = Open {
+            paths: Vec::new().into_iter(),
+            select: self.select,
+            opener: self.opener,
+            on_missing: self.on_missing,
+            detect_changes: self.detect_changes,
+        };
+        let mut sources = Vec::with_capacity(self.paths.len());
+        for path in self.paths {
+            sources.extend(open.source(path)?);
+        }
+        Ok(MultiReader::new(sources.into_iter()))
+    }
+
+    /// Like `into_reader`, but a background thread opens files ahead of the
+    /// one being read, hiding the latency of slow file systems. At most
+    /// `max_open` files are open at once, counting the one being read, so
+    /// at least 2 are needed. The thread stops when the chain is dropped.
+    pub fn into_prefetched(self, max_open: usize) -> MultiReader<Fallible<Source>, Prefetched> {
+        assert!(max_open >= 2, "prefetching needs at least 2 open files");
+        // Besides those in the channel, one file is being read and the
+        // thread may hold one waiting for room.
+        let (tx, rx) = mpsc::sync_channel(max_open - 2);
+        let open = self.into_reader().into_sources();
+        thread::spawn(move || {
+            for source in open {
+                if tx.send(source).is_err() {
+                    break;
+                }
+            }
+        });
+        MultiReader::new(Prefetched { sources: rx.into_iter() })
+    }
+
+    /// Stats every file now and chains them as a seekable chain of known
+    /// length, opening each one when a read needs it. Files are filtered
+    /// and deduplicated now too.
+    pub fn into_seekable(mut self)
+        -> io::Result<SeekableMultiReader<File, impl Fn(usize) -> io::Result<File>>>
+    {
+        let mut paths = Vec::with_capacity(self.paths.len());
+        let mut lengths = Vec::with_capacity(self.paths.len());
+        for path in self.paths {
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    if e.kind() == io::ErrorKind::NotFound {
+                        if let Some(ref mut warn) = self.on_missing {
+                            warn(&path);
+                            continue;
+                        }
+                    }
+                    return Err(with_path(e, &path));
+                }
+            };
+            if self.select.keeps(&path, &metadata)? {
+                paths.push(path);
+                lengths.push(metadata.len());
+            }
+        }
+        let opener = self.opener;
+        Ok(SeekableMultiReader::new(lengths, move |i| opener.open(&paths[i])))
+    }
+}
+
+/// Source iterator behind `Files::into_reader`.
+pub struct Open {
+    paths: vec::IntoIter<PathBuf>,
+    select: Select,
+    opener: Opener,
+    on_missing: Option<OnMissing>,
+    detect_changes: bool,
+}
+
+impl Open {
+    /// Opens the file at `path`, if it makes it into the chain.
+    fn source(&mut self, path: PathBuf) -> io::Result<Option<Source>> {
+        let file = match self.opener.open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                if e.kind() == io::ErrorKind::NotFound {
+                    if let Some(ref mut warn) = self.on_missing {
+                        warn(&path);
+                        return Ok(None);
+                    }
+                }
+                return Err(e);
+            }
+        };
+        let mut stamp = None;
+        if !self.select.keeps_all() || self.detect_changes {
+            let metadata = file.metadata().map_err(|e| with_path(e, &path))?;
+            if !self.select.keeps(&path, &metadata)? {
+                return Ok(None);
+            }
+            if self.detect_changes && metadata.is_file() {
+                stamp = Some(Stamp::of(&metadata));
+            }
+        }
+        Ok(Some(Source { file, path, stamp }))
+    }
+}
+
+impl Iterator for Open {
+    type Item = Fallible<Source>;
+
+    fn next(&mut self) -> Option<Fallible<Source>> {
+        loop {
+            let path = self.paths.next()?;
+            match self.source(path) {
+                Ok(None) => continue,
+                Ok(Some(source)) => return Some(Fallible::from(Ok(source))),
+                Err(e) => return Some(Fallible::from(Err(e))),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if !self.select.keeps_all() || self.on_missing.is_some() {
+            (0, Some(self.paths.len()))
+        } else {
+            self.paths.size_hint()
+        }
+    }
+}
+
+/// Iterator receiving files opened by a background thread.
+pub struct Prefetched {
+    sources: mpsc::IntoIter<Fallible<Source>>,
+}
+
+impl Iterator for Prefetched {
+    type Item = Fallible<Source>;
+
+    fn next(&mut self) -> Option<Fallible<Source>> {
+        self.sources.next()
+    }
+}
+
+/// An open file of a chain, see `Files::into_reader`.
+pub struct Source {
+    file: File,
+    path: PathBuf,
+    // Taken when opened, to compare with at the end of the file.
+    stamp: Option<Stamp>,
+}
+
+impl Source {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_ref(&self) -> &File {
+        &self.file
+    }
+
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            if let Some(stamp) = self.stamp.take() {
+                let now = self.file.metadata().map_err(|e| with_path(e, &self.path))?;
+                if Stamp::of(&now) != stamp {
+                    let msg = format!("{}: changed while being read", self.path.display());
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    fn of(metadata: &fs::Metadata) -> Stamp {
+        Stamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, rest_a) = split_digits(a);
+                let (y, rest_b) = split_digits(b);
+                let (tx, ty) = (trim_zeros(x), trim_zeros(y));
+                // Equal numbers spelled with more leading zeros come last.
+                let order = tx.len().cmp(&ty.len()).then(tx.cmp(ty)).then(x.len().cmp(&y.len()));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a = rest_a;
+                b = rest_b;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+fn split_digits(s: &[u8]) -> (&[u8], &[u8]) {
+    s.split_at(s.iter().position(|c| !c.is_ascii_digit()).unwrap_or(s.len()))
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    &digits[digits.iter().position(|&c| c != b'0').unwrap_or(digits.len())..]
+}
+
+/// Which files make it into a chain.
+struct Select {
+    filters: Vec<Filter>,
+    seen: Option<HashSet<Identity>>,
+}
+
+impl Select {
+    fn keeps_all(&self) -> bool {
+        self.filters.is_empty() && self.seen.is_none()
+    }
+
+    fn keeps(&mut self, path: &Path, metadata: &fs::Metadata) -> io::Result<bool> {
+        if !self.filters.iter().all(|f| f(metadata)) {
+            return Ok(false);
+        }
+        match self.seen {
+            Some(ref mut seen) => Ok(seen.insert(identity(path, metadata)?)),
+            None => Ok(true),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn identity(_: &Path, metadata: &fs::Metadata) -> io::Result<Identity> {
+    use std::os::unix::fs::MetadataExt;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn identity(path: &Path, _: &fs::Metadata) -> io::Result<Identity> {
+    fs::canonicalize(path).map_err(|e| with_path(e, path))
+}
+
+/// How files are opened.
+#[derive(Clone, Copy)]
+struct Opener {
+    attempts: usize,
+    initial_delay: Duration,
+    max_delay: Duration,
+    lock: bool,
+}
+
+impl Opener {
+    fn open(&self, path: &Path) -> io::Result<File> {
+        let file = self.open_with(path, |p| File::open(p))?;
+        if self.lock {
+            file.lock_shared().map_err(|e| with_path(e, path))?;
+        }
+        Ok(file)
+    }
+
+    fn open_with<F>(&self, path: &Path, mut open: F) -> io::Result<File>
+        where F: FnMut(&Path) -> io::Result<File>
+    {
+        let mut delay = self.initial_delay;
+        let mut attempt = 1;
+        loop {
+            match open(path) {
+                Err(ref e) if attempt < self.attempts && is_transient(e) => {}
+                result => return result.map_err(|e| with_path(e, path)),
+            }
+            attempt += 1;
+            if delay > Duration::from_millis(0) {
+                thread::sleep(delay);
+            }
+            delay = ::std::cmp::min(delay * 2, self.max_delay);
+        }
+    }
+}
+
+fn is_transient(e: &io::Error) -> bool {
+    // EMFILE and ENFILE, the same on Linux, macOS and the BSDs.
+    #[cfg(unix)]
+    {
+        if let Some(23) | Some(24) = e.raw_os_error() {
+            return true;
+        }
+    }
+    matches!(e.kind(),
+             io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut |
+             io::ErrorKind::ResourceBusy | io::ErrorKind::StaleNetworkFileHandle)
+}
+
+fn with_path(e: io::Error, path: &Path) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::fs;
+    use std::fs::File;
+    use std::io;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+    use std::cmp::Ordering;
+    use super::{from_dir, from_paths, natural_cmp, Symlinks};
+    use testing::temp_dir as dir;
+
+    #[test]
+    fn test_into_reader() {
+        let d = dir("into_reader", &[("a", b"one\n"), ("b", b"two\n")]);
+        let paths = vec![d.join("a"), d.join("missing"), d.join("b")];
+        let mut m = from_paths(paths).into_reader();
+        let mut out = String::new();
+        let err = m.read_to_string(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().starts_with(&d.join("missing").display().to_string()));
+        assert_eq!(m.read_to_string(&mut out).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(out, "one\n");
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_skip_empty() {
+        let d = dir("skip_empty", &[("a", b"x"), ("b", b""), ("c", b"y")]);
+        let paths = vec![d.join("b"), d.join("a"), d.join("b"), d.join("c")];
+        let mut m = from_paths(paths.clone()).skip_empty().into_reader().separator(b"|");
+        let mut out = String::new();
+        m.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "x|y");
+        assert_eq!(from_paths(paths).skip_empty().into_seekable().unwrap().len(), 2);
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_filter_by_metadata() {
+        let d = dir("filter", &[("old", b"old"), ("big", b"too big"), ("new", b"new")]);
+        let week_ago = SystemTime::now() - Duration::from_secs(7 * 24 * 3600);
+        File::options().write(true).open(d.join("old")).unwrap().set_modified(week_ago).unwrap();
+        let paths = vec![d.join("old"), d.join("big"), d.join("new")];
+        let day_ago = SystemTime::now() - Duration::from_secs(24 * 3600);
+        let files = || {
+            from_paths(paths.clone())
+                .filter(move |m| m.modified().unwrap() > day_ago)
+                .filter(|m| m.len() < 5)
+        };
+        let mut out = String::new();
+        files().into_reader().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "new");
+        assert_eq!(files().into_seekable().unwrap().len(), 3);
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_dedupe() {
+        let d = dir("dedupe", &[("a", b"a"), ("b", b"b")]);
+        fs::hard_link(d.join("a"), d.join("a2")).unwrap();
+        let paths = vec![d.join("a"), d.join("b"), d.join(".").join("a"), d.join("a2"), d.join("b")];
+        let mut out = String::new();
+        from_paths(paths.clone()).dedupe().into_reader().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "ab");
+        assert_eq!(from_paths(paths).dedupe().into_seekable().unwrap().len(), 2);
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_ordering() {
+        let d = dir("ordering", &[("b", b"b"), ("c", b"c"), ("a", b"a"), ("sub/d", b"d")]);
+        let read = |files: super::Files| {
+            let mut out = String::new();
+            files.into_reader().read_to_string(&mut out).unwrap();
+            out
+        };
+        assert_eq!(read(from_dir(&d).files().unwrap()), "abcd");
+        assert_eq!(read(from_dir(&d).files().unwrap().sort_by(|x, y| y.cmp(x))), "dcba");
+        fs::remove_dir_all(d.join("sub")).unwrap();
+
+        let now = SystemTime::now();
+        for (name, age) in [("a", 1), ("b", 3), ("c", 2)] {
+            let file = File::options().write(true).open(d.join(name)).unwrap();
+            file.set_modified(now - Duration::from_secs(age * 60)).unwrap();
+        }
+        assert_eq!(read(from_dir(&d).files().unwrap().sort_by_mtime().unwrap()), "bca");
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_natural_order() {
+        let mut names = vec!["part10", "file.10.gz", "part2", "part02", "file.9.gz", "part", "p1q"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, vec!["file.9.gz", "file.10.gz", "p1q", "part", "part2", "part02", "part10"]);
+        assert_eq!(natural_cmp("a01b", "a1c"), Ordering::Greater);
+
+        let d = dir("natural", &[("part10", b"c"), ("part9", b"b"), ("part1", b"a")]);
+        let mut out = String::new();
+        from_dir(&d).files().unwrap().into_reader().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "abc");
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_depth_and_hidden() {
+        let files: &[(&str, &[u8])] =
+            &[("a", b"a"), (".h", b"h"), ("x/b", b"b"), ("x/y/c", b"c"), (".x/d", b"d")];
+        let d = dir("depth", files);
+        let read = |files: super::Files| {
+            let mut out = String::new();
+            files.into_reader().read_to_string(&mut out).unwrap();
+            out
+        };
+        assert_eq!(read(from_dir(&d).files().unwrap()), "hdabc");
+        assert_eq!(read(from_dir(&d).include_hidden(false).files().unwrap()), "abc");
+        assert_eq!(read(from_dir(&d).include_hidden(false).max_depth(2).files().unwrap()), "ab");
+        assert_eq!(read(from_dir(&d).max_depth(1).files().unwrap()), "ha");
+        assert_eq!(read(from_dir(&d).max_depth(0).files().unwrap()), "");
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_symlinks() {
+        use std::os::unix::fs::symlink;
+        let d = dir("symlinks", &[("a", b"a"), ("sub/b", b"b")]);
+        symlink(d.join("a"), d.join("sub/link")).unwrap();
+        symlink(&d, d.join("sub/loop")).unwrap();
+        let paths = |symlinks| {
+            from_dir(&d).symlinks(symlinks).files().map(|f| {
+                f.paths().iter().map(|p| p.strip_prefix(&d).unwrap().to_owned()).collect::<Vec<_>>()
+            })
+        };
+        let followed = paths(Symlinks::Follow).unwrap();
+        assert_eq!(followed, vec![PathBuf::from("a"), "sub/b".into(), "sub/link".into()]);
+        assert_eq!(paths(Symlinks::Skip).unwrap(), vec![PathBuf::from("a"), "sub/b".into()]);
+        assert_eq!(paths(Symlinks::Error).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_open_retries() {
+        let d = dir("open_retries", &[("a", b"a")]);
+        let files = from_paths(vec![d.join("a")])
+            .open_attempts(3)
+            .open_backoff(Duration::from_millis(1), Duration::from_millis(2));
+        let mut calls = 0;
+        let flaky = |path: &Path| {
+            calls += 1;
+            match calls {
+                1 => Err(io::Error::from_raw_os_error(24)),
+                2 => Err(io::ErrorKind::WouldBlock.into()),
+                _ => File::open(path),
+            }
+        };
+        files.opener.open_with(&d.join("a"), flaky).unwrap();
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let err = files.opener.open_with(&d.join("a"), |_| {
+            calls += 1;
+            Err(io::ErrorKind::TimedOut.into())
+        });
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let err = files.opener.open_with(&d.join("missing"), |p| {
+            calls += 1;
+            File::open(p)
+        });
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(calls, 1);
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_skip_missing() {
+        use std::sync::{Arc, Mutex};
+        let d = dir("skip_missing", &[("a", b"a"), ("c", b"c")]);
+        let paths = vec![d.join("a"), d.join("b"), d.join("c")];
+        let missing = Arc::new(Mutex::new(Vec::new()));
+        let files = || {
+            let missing = missing.clone();
+            from_paths(paths.clone()).skip_missing(move |p| missing.lock().unwrap().push(p.to_owned()))
+        };
+        let mut out = String::new();
+        files().into_reader().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "ac");
+        assert_eq!(files().into_seekable().unwrap().len(), 2);
+        assert_eq!(*missing.lock().unwrap(), vec![d.join("b"), d.join("b")]);
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_lock_shared() {
+        use std::fs::TryLockError;
+        let d = dir("lock_shared", &[("a", b"ab"), ("b", b"c")]);
+        let mut m = from_paths(vec![d.join("a"), d.join("b")]).lock_shared().into_reader();
+        let writer = File::options().write(true).open(d.join("a")).unwrap();
+        let mut buf = [0; 1];
+        m.read_exact(&mut buf).unwrap();
+        assert!(matches!(writer.try_lock(), Err(TryLockError::WouldBlock)));
+        writer.try_lock_shared().unwrap();
+        writer.unlock().unwrap();
+        m.read_to_end(&mut Vec::new()).unwrap();
+        writer.try_lock().unwrap();
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_detect_changes() {
+        use std::io::Write;
+        let d = dir("detect_changes", &[("a", b"abc"), ("b", b"def")]);
+        let mut m = from_paths(vec![d.join("a"), d.join("b")]).detect_changes().into_reader();
+        let mut buf = [0; 2];
+        m.read_exact(&mut buf).unwrap();
+        File::options().append(true).open(d.join("a")).unwrap().write_all(b"x").unwrap();
+        let mut out = Vec::new();
+        let err = m.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().ends_with("a: changed while being read"));
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"cxdef");
+
+        let mut paths = Vec::new();
+        from_paths(vec![d.join("a"), d.join("b")])
+            .detect_changes()
+            .into_reader()
+            .map_each(|_, s| {
+                paths.push(s.get_ref().unwrap().path().to_owned());
+                s
+            })
+            .read_to_end(&mut Vec::new())
+            .unwrap();
+        assert_eq!(paths, vec![d.join("a"), d.join("b")]);
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_into_snapshot() {
+        let d = dir("snapshot", &[("a", b"ab"), ("b", b"cd")]);
+        let paths = vec![d.join("a"), d.join("missing"), d.join("b")];
+        let mut m = from_paths(paths.clone()).skip_missing(|_| {}).into_snapshot(3).unwrap();
+        fs::remove_file(d.join("b")).unwrap();
+        let mut out = String::new();
+        m.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "abcd");
+
+        let err = from_paths(paths.clone()).into_snapshot(2).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = from_paths(paths).into_snapshot(3).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_into_prefetched() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        let contents: Vec<(String, Vec<u8>)> =
+            (0..10).map(|i| (format!("f{}", i), vec![b'a' + i as u8; 3])).collect();
+        let files: Vec<(&str, &[u8])> =
+            contents.iter().map(|(name, data)| (&name[..], &data[..])).collect();
+        let d = dir("prefetched", &files);
+        let mut paths: Vec<_> = contents.iter().map(|(name, _)| d.join(name)).collect();
+        paths.insert(5, d.join("missing"));
+
+        // Counts the files opened so far, as each one is filtered once open.
+        let opened = Arc::new(AtomicUsize::new(0));
+        let counter = opened.clone();
+        let mut m = from_paths(paths)
+            .filter(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                true
+            })
+            .into_prefetched(4);
+        // The thread blocks once `max_open` files are open, so the count
+        // reaches the bound and stays there until the chain moves on.
+        let wait_for = |n| {
+            for _ in 0..1000 {
+                if opened.load(Ordering::SeqCst) >= n {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+            assert_eq!(opened.load(Ordering::SeqCst), n);
+        };
+        let mut buf = [0; 3];
+        m.read_exact(&mut buf).unwrap();
+        wait_for(4);
+        m.read_exact(&mut buf).unwrap();
+        wait_for(5);
+
+        let mut out = buf.to_vec();
+        let err = m.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(m.read_to_end(&mut out).unwrap_err().kind(), io::ErrorKind::NotFound);
+        let expected: Vec<u8> = (1..5).flat_map(|i| vec![b'a' + i; 3]).collect();
+        assert_eq!(out, expected);
+        fs::remove_dir_all(d).unwrap();
+    }
+
+    #[test]
+    fn test_into_seekable() {
+        let d = dir("into_seekable", &[("a", b"abc"), ("b", b""), ("c", b"defg")]);
+        let mut m = from_paths(vec![d.join("a"), d.join("b"), d.join("c")])
+            .into_seekable()
+            .unwrap();
+        assert_eq!(m.len(), 7);
+        m.seek(SeekFrom::Start(2)).unwrap();
+        let mut out = String::new();
+        m.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "cdefg");
+
+        let err = from_paths(vec![d.join("none")]).into_seekable().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        fs::remove_dir_all(d).unwrap();
+    }
+}
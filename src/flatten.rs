@@ -0,0 +1,183 @@
+//! Chains of chains, read as a single flat chain.
+//!
+//! Nesting `MultiReader`s works as is, but every read then goes through each
+//! level, and `source_index` only tells which nested chain is being read.
+//! `flatten` instead pulls the sources out of every nested chain, so that
+//! indices and offsets refer to the innermost sources:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//! use multi_reader::flatten::flatten;
+//!
+//! fn main() {
+//!     let first = MultiReader::new(vec![&b"ab"[..], &b"c"[..]].into_iter());
+//!     let second = MultiReader::new(vec![&b"de"[..]].into_iter());
+//!     let mut reader = flatten(vec![first, second]);
+//!     let mut buf = [0; 4];
+//!     assert_eq!(reader.read(&mut buf).unwrap(), 2);
+//!     assert_eq!(reader.read(&mut buf).unwrap(), 1);
+//!     assert_eq!(reader.read(&mut buf).unwrap(), 2);
+//!     assert_eq!(reader.source_index(), 2);
+//! }
+//! ```
+
+use std::io;
+use std::io::Read;
+use std::iter;
+use std::option;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::vec;
+
+use MultiReader;
+
+/// A reader made of several sources read one after another.
+pub trait Sources {
+    type Source: Read;
+    type Iter: Iterator<Item = Self::Source>;
+
+    /// The sources not finished yet, in reading order.
+    fn into_sources(self) -> Self::Iter;
+
+    /// The number of bytes already read from the first of those sources.
+    fn source_offset(&self) -> u64 {
+        0
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Sources for MultiReader<R, I> {
+    type Source = R;
+    type Iter = iter::Chain<option::IntoIter<R>, I>;
+
+    /// # Panics
+    ///
+    /// If bytes have been peeked from the chain, as they would be lost.
+    fn into_sources(self) -> Self::Iter {
+        assert!(self.peeked.is_empty(), "cannot flatten a chain with peeked bytes");
+        self.current.into_iter().chain(self.readers)
+    }
+
+    fn source_offset(&self) -> u64 {
+        if self.current.is_some() { self.offset } else { 0 }
+    }
+}
+
+impl<R: Read> Sources for io::Chain<R, R> {
+    type Source = R;
+    type Iter = vec::IntoIter<R>;
+
+    fn into_sources(self) -> Self::Iter {
+        let (first, second) = self.into_inner();
+        vec![first, second].into_iter()
+    }
+}
+
+/// Source iterator behind `flatten`.
+pub struct Flatten<I, S: Sources> {
+    chains: I,
+    current: Option<S::Iter>,
+    // Offset of the next source, shared with the flattened chain.
+    entry_offset: Arc<AtomicU64>,
+    first_offset: u64,
+}
+
+impl<I: Iterator<Item = S>, S: Sources> Iterator for Flatten<I, S> {
+    type Item = S::Source;
+
+    fn next(&mut self) -> Option<S::Source> {
+        loop {
+            if let Some(source) = self.current.as_mut().and_then(|c| c.next()) {
+                self.entry_offset.store(self.first_offset, Ordering::SeqCst);
+                self.first_offset = 0;
+                return Some(source);
+            }
+            let chain = self.chains.next()?;
+            self.first_offset = chain.source_offset();
+            self.current = Some(chain.into_sources());
+        }
+    }
+}
+
+/// Chains the sources of every chain in `chains`.
+pub fn flatten<S, T>(chains: T) -> MultiReader<S::Source, Flatten<T::IntoIter, S>>
+    where S: Sources,
+          T: IntoIterator<Item = S>
+{
+    let entry_offset = Arc::new(AtomicU64::new(0));
+    MultiReader::new(Flatten {
+            chains: chains.into_iter(),
+            current: None,
+            entry_offset: entry_offset.clone(),
+            first_offset: 0,
+        })
+        .entering_at(entry_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::vec;
+    use super::flatten;
+    use MultiReader;
+
+    #[test]
+    fn test_flatten_chains() {
+        let chains = vec![(&b"ab"[..]).chain(&b""[..]), (&b"c"[..]).chain(&b"d"[..])];
+        let mut m = flatten(chains);
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abcd");
+        assert_eq!(m.source_index(), 4);
+    }
+
+    #[test]
+    fn test_flatten_partially_read() {
+        let mut first = MultiReader::new(vec![&b"abc"[..], &b"d"[..]].into_iter());
+        assert_eq!(first.read(&mut [0; 2]).unwrap(), 2);
+        let empty = MultiReader::new(Vec::new().into_iter());
+        let mut last = MultiReader::new(vec![&b"xef"[..]].into_iter());
+        assert_eq!(last.read(&mut [0; 1]).unwrap(), 1);
+        let mut m = flatten(vec![first, empty, last]);
+        let mut buf = [0; 4];
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!((m.source_index(), m.source_offset()), (0, 3));
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!(m.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ef");
+        assert_eq!((m.source_index(), m.source_offset()), (2, 3));
+    }
+
+    fn partly_read(sources: Vec<&'static [u8]>)
+                   -> MultiReader<&'static [u8], vec::IntoIter<&'static [u8]>> {
+        let mut m = MultiReader::new(sources.into_iter());
+        assert_eq!(m.read(&mut [0; 1]).unwrap(), 1);
+        m
+    }
+
+    #[test]
+    fn test_take_sources_of_flattened_chain() {
+        let mut m = flatten(vec![partly_read(vec![b"abc"]), partly_read(vec![b"de", b"f"])]);
+        let mut buf = [0; 4];
+        {
+            let mut batch = m.take_sources(2);
+            assert_eq!(batch.read(&mut buf).unwrap(), 2);
+            assert_eq!((batch.source_index(), batch.source_offset()), (0, 3));
+            assert_eq!(batch.read(&mut buf).unwrap(), 1);
+            assert_eq!((batch.source_index(), batch.source_offset()), (1, 2));
+        }
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!((m.source_index(), m.source_offset()), (2, 1));
+    }
+
+    #[test]
+    fn test_split_off_flattened_chain() {
+        let m = flatten(vec![partly_read(vec![b"abc"]), partly_read(vec![b"de"])]);
+        let mut rest = m.split_off();
+        let mut buf = [0; 4];
+        assert_eq!(rest.read(&mut buf).unwrap(), 2);
+        assert_eq!(rest.read(&mut buf).unwrap(), 1);
+        assert_eq!((rest.source_index(), rest.source_offset()), (1, 2));
+    }
+}
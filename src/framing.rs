@@ -0,0 +1,217 @@
+//! Length-prefixed framing of sources.
+//!
+//! `Encoder` emits every source as a frame made of the source index, the
+//! payload length (both little-endian, `u32` or `u64` wide) and the payload.
+//! `Decoder` splits such a stream back into frames:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use multi_reader::framing::{Decoder, Encoder, Width};
+//!
+//! fn main() {
+//!     let blobs = vec![&b"first"[..], &b""[..], &b"third"[..]];
+//!     let encoded = Encoder::new(blobs.into_iter(), Width::U32);
+//!     let frames: Vec<_> = Decoder::new(encoded, Width::U32).map(|f| f.unwrap()).collect();
+//!     assert_eq!(frames.len(), 3);
+//!     assert_eq!(frames[2].index, 2);
+//!     assert_eq!(frames[2].data, b"third");
+//! }
+//! ```
+//!
+//! The length has to be known before a payload is written, so the encoder
+//! buffers one source at a time in memory.
+
+use std::io;
+use std::io::Read;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Width {
+    U32,
+    U64,
+}
+
+impl Width {
+    fn size(self) -> usize {
+        match self {
+            Width::U32 => 4,
+            Width::U64 => 8,
+        }
+    }
+
+    fn encode(self, value: u64, out: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Width::U32 => {
+                if value > u32::MAX as u64 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "value does not fit a u32 frame field"));
+                }
+                out.extend_from_slice(&(value as u32).to_le_bytes());
+            }
+            Width::U64 => out.extend_from_slice(&value.to_le_bytes()),
+        }
+        Ok(())
+    }
+
+    fn decode(self, bytes: &[u8]) -> u64 {
+        let mut le = [0; 8];
+        le[..bytes.len()].copy_from_slice(bytes);
+        u64::from_le_bytes(le)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub index: u64,
+    pub data: Vec<u8>,
+}
+
+pub struct Encoder<I> {
+    readers: I,
+    width: Width,
+    index: u64,
+    frame: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read, I: Iterator<Item = R>> Encoder<I> {
+    pub fn new(readers: I, width: Width) -> Encoder<I> {
+        Encoder {
+            readers,
+            width,
+            index: 0,
+            frame: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn next_frame(&mut self) -> io::Result<bool> {
+        let mut reader = match self.readers.next() {
+            Some(r) => r,
+            None => return Ok(false),
+        };
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        self.frame.clear();
+        self.pos = 0;
+        self.width.encode(self.index, &mut self.frame)?;
+        self.width.encode(data.len() as u64, &mut self.frame)?;
+        self.frame.extend_from_slice(&data);
+        self.index += 1;
+        Ok(true)
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Read for Encoder<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.frame.len() && !self.next_frame()? {
+            return Ok(0);
+        }
+        let n = ::std::cmp::min(buf.len(), self.frame.len() - self.pos);
+        buf[..n].copy_from_slice(&self.frame[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+pub struct Decoder<R> {
+    inner: R,
+    width: Width,
+    done: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R, width: Width) -> Decoder<R> {
+        Decoder {
+            inner,
+            width,
+            done: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads the next frame, or `None` if the stream ends at a frame boundary.
+    pub fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        let size = self.width.size();
+        let mut header = [0; 16];
+        let mut filled = 0;
+        while filled < 2 * size {
+            match self.inner.read(&mut header[filled..2 * size]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(truncated()),
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let index = self.width.decode(&header[..size]);
+        let len = self.width.decode(&header[size..2 * size]);
+
+        let mut data = Vec::new();
+        let read = (&mut self.inner).take(len).read_to_end(&mut data)?;
+        if (read as u64) < len {
+            return Err(truncated());
+        }
+        Ok(Some(Frame { index, data }))
+    }
+}
+
+impl<R: Read> Iterator for Decoder<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<io::Result<Frame>> {
+        if self.done {
+            return None;
+        }
+        let frame = self.read_frame();
+        if let Ok(None) | Err(_) = frame {
+            self.done = true;
+        }
+        frame.transpose()
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use super::{Decoder, Encoder, Frame, Width};
+
+    #[test]
+    fn test_encode_layout() {
+        let mut e = Encoder::new(vec![&b"ab"[..], &b""[..]].into_iter(), Width::U32);
+        let mut out = Vec::new();
+        e.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"\x00\x00\x00\x00\x02\x00\x00\x00ab\x01\x00\x00\x00\x00\x00\x00\x00");
+    }
+
+    #[test]
+    fn test_round_trip_u64() {
+        let blobs: Vec<Vec<u8>> = vec![vec![1; 3], vec![], vec![2; 5000]];
+        let e = Encoder::new(blobs.iter().map(|b| &b[..]), Width::U64);
+        let frames: Vec<Frame> = Decoder::new(e, Width::U64).map(|f| f.unwrap()).collect();
+        assert_eq!(frames.len(), 3);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.index, i as u64);
+            assert_eq!(frame.data, blobs[i]);
+        }
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let mut d = Decoder::new(&b"\x00\x00\x00\x00\x05\x00\x00\x00abc"[..], Width::U32);
+        let err = d.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(d.next().is_none());
+
+        let mut d = Decoder::new(&b"\x00\x00"[..], Width::U32);
+        assert_eq!(d.next().unwrap().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
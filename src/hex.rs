@@ -0,0 +1,186 @@
+//! Streaming hex decoding across the whole chain.
+//!
+//! Digit pairs may straddle source boundaries and whitespace is ignored, so
+//! fragments of a hexdump can be re-joined as they are:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::{hex, MultiReader};
+//!
+//! fn main() {
+//!     let fragments = vec![&b"68 65 6c\n6"[..], &b"c 6f"[..]];
+//!     let mut reader = hex::Decoder::new(MultiReader::new(fragments.into_iter()));
+//!     let mut s = String::new();
+//!     reader.read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "hello");
+//! }
+//! ```
+//!
+//! Invalid digits are reported as `InvalidData` errors naming the source and
+//! the offset within it.
+
+use std::io;
+use std::io::Read;
+
+use MultiReader;
+
+const INPUT_SIZE: usize = 4096;
+
+pub struct Decoder<R, I> {
+    inner: MultiReader<R, I>,
+    input: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    high: Option<u8>,
+    // Failure kept for the next read, so that the bytes decoded before it
+    // are returned first.
+    error: Option<io::Error>,
+}
+
+impl<R: Read, I: Iterator<Item = R>> Decoder<R, I> {
+    pub fn new(inner: MultiReader<R, I>) -> Decoder<R, I> {
+        Decoder {
+            inner,
+            input: vec![0; INPUT_SIZE].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+            high: None,
+            error: None,
+        }
+    }
+
+    pub fn into_inner(self) -> MultiReader<R, I> {
+        self.inner
+    }
+
+    fn fill(&mut self) -> io::Result<usize> {
+        self.pos = 0;
+        self.len = self.inner.read(&mut self.input)?;
+        Ok(self.len)
+    }
+
+    fn invalid_digit(&self, c: u8) -> io::Error {
+        // Separator bytes are at offset 0 of the source they precede.
+        let offset = self.inner.source_offset().saturating_sub((self.len - self.pos) as u64);
+        io::Error::new(io::ErrorKind::InvalidData,
+                       format!("invalid hex digit 0x{:02x} in source {} at offset {}",
+                               c,
+                               self.inner.source_index(),
+                               offset))
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Read for Decoder<R, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let mut written = 0;
+        while written < buf.len() {
+            if self.pos == self.len && (written > 0 || self.fill()? == 0) {
+                break;
+            }
+            let c = self.input[self.pos];
+            let nibble = match c {
+                b'0'..=b'9' => c - b'0',
+                b'a'..=b'f' => c - b'a' + 10,
+                b'A'..=b'F' => c - b'A' + 10,
+                b' ' | b'\t' | b'\r' | b'\n' => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ if written == 0 => return Err(self.invalid_digit(c)),
+                _ => {
+                    self.error = Some(self.invalid_digit(c));
+                    break;
+                }
+            };
+            self.pos += 1;
+            match self.high.take() {
+                Some(high) => {
+                    buf[written] = high << 4 | nibble;
+                    written += 1;
+                }
+                None => self.high = Some(nibble),
+            }
+        }
+        if written == 0 && !buf.is_empty() && self.high.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "odd number of hex digits at end of input"));
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use super::Decoder;
+    use MultiReader;
+
+    fn decode(sources: Vec<&[u8]>) -> io::Result<Vec<u8>> {
+        let mut d = Decoder::new(MultiReader::new(sources.into_iter()));
+        let mut out = Vec::new();
+        d.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(vec![b"48656C6c6f"]).unwrap(), b"Hello");
+        assert_eq!(decode(vec![b"48 65\n6c\r\n6c\t6f\n"]).unwrap(), b"Hello");
+        assert_eq!(decode(vec![]).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_pairs_straddle_sources() {
+        assert_eq!(decode(vec![b"4", b"86", b"", b"5"]).unwrap(), b"He");
+    }
+
+    #[test]
+    fn test_small_output_buffer() {
+        let mut d = Decoder::new(MultiReader::new(vec![&b"0102"[..], &b"03"[..]].into_iter()));
+        let mut buf = [0; 1];
+        for expected in 1..4 {
+            assert_eq!(d.read(&mut buf).unwrap(), 1);
+            assert_eq!(buf[0], expected);
+        }
+        assert_eq!(d.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_invalid_digit_attribution() {
+        let err = decode(vec![b"0102", b"", b"03 0g"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "invalid hex digit 0x67 in source 2 at offset 4");
+    }
+
+    #[test]
+    fn test_invalid_separator() {
+        let m = MultiReader::new(vec![&b"01"[..], &b"02"[..]].into_iter()).separator(b"zz");
+        let mut out = Vec::new();
+        let err = Decoder::new(m).read_to_end(&mut out).unwrap_err();
+        assert_eq!(out, [1]);
+        assert_eq!(err.to_string(), "invalid hex digit 0x7a in source 1 at offset 0");
+    }
+
+    #[test]
+    fn test_bytes_before_an_error_are_returned() {
+        let mut d = Decoder::new(MultiReader::new(vec![&b"4869zz"[..]].into_iter()));
+        let mut buf = [0; 8];
+        assert_eq!(d.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"Hi");
+        let err = d.read(&mut buf).unwrap_err();
+        assert_eq!(err.to_string(), "invalid hex digit 0x7a in source 0 at offset 4");
+        assert!(d.read(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_odd_digits() {
+        let err = decode(vec![b"01", b"0"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "odd number of hex digits at end of input");
+    }
+}
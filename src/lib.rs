@@ -19,46 +19,1196 @@
 
 #![crate_name = "multi_reader"]
 
+use std::collections::VecDeque;
 use std::io;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use std::vec;
 
+pub mod aligned;
+pub mod array;
+pub mod base64;
+pub mod bits;
+pub mod buffered;
+#[cfg(feature = "byteorder")]
+pub mod byteorder;
+mod builder;
+pub mod checked;
+pub mod chunked;
+pub mod empty;
+pub mod encoding;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod files;
+pub mod flatten;
+pub mod framing;
+pub mod hex;
+pub mod lines;
+#[cfg(feature = "multipart")]
+pub mod multipart;
+pub mod mux;
+pub mod newline;
+pub mod observe;
+#[cfg(any(unix, windows))]
+pub mod positional;
+pub mod process;
+pub mod progress;
+pub mod range;
+pub mod reconnect;
+pub mod pool;
+mod rng;
+pub mod scoped;
+pub mod seekable;
+pub mod shared;
+pub mod shutdown;
+pub mod slices;
+#[cfg(feature = "tar")]
+pub mod tar;
+pub mod testing;
+pub mod utf8;
+
+pub use builder::MultiReaderBuilder;
+use empty::{Probed, SkipEmpty};
+use aligned::Aligned;
+use observe::{Heartbeat, Inspect, Tee};
+use pool::BufferPool;
+use rng::XorShift;
+use shutdown::{ShutdownHandle, Stop};
+use utf8::{Chars, ValidateUtf8};
+
+/// What to do when a source returns `Ok(0)`, see
+/// `MultiReader::empty_read_policy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptyRead {
+    /// Treat it as the end of the source and move on to the next one.
+    Advance,
+    /// Read the same source again.
+    Retry,
+    /// Return `Ok(0)` to the caller and stay on the same source.
+    Return,
+}
+
+type EmptyReadPolicy = Box<dyn FnMut(usize, usize) -> EmptyRead + Send>;
+type Separator = Box<dyn FnMut(usize) -> Box<dyn Read + Send> + Send>;
+
+/// Reads its sources one after another.
+///
+/// EOF is sticky: once `read` has returned `Ok(0)` for a non-empty buffer,
+/// neither the iterator nor any source is touched again, even if they would
+/// produce more data later, unless the chain is explicitly `rearm`ed. An
+/// `Ok(0)` passed through by `EmptyRead::Return` is not EOF.
 pub struct MultiReader<R, I> {
     readers: I,
     current: Option<R>,
+    index: usize,
+    offset: u64,
+    served: usize,
+    served_offset: u64,
+    peeked: PeekBuffer,
+    eof: bool,
+    empty_reads: usize,
+    empty_read_policy: Option<EmptyReadPolicy>,
+    separator: Option<Separator>,
+    separating: Option<Box<dyn Read + Send>>,
+    // Bytes copied for every separator made by `separator`.
+    separator_len: usize,
+    pool: Arc<BufferPool>,
+    memory_limit: Option<usize>,
+    stop: Stop,
+    // Set by `flatten` to the offset each source is entered at, as the
+    // sources of a partly read chain do not start at offset 0.
+    entry_offset: Option<Arc<AtomicU64>>,
 }
 
 impl<R: Read, I: Iterator<Item = R>> MultiReader<R, I> {
     pub fn new(mut readers: I) -> MultiReader<R, I> {
         let current = readers.next();
         MultiReader {
-            readers: readers,
-            current: current,
+            readers,
+            current,
+            index: 0,
+            offset: 0,
+            served: 0,
+            served_offset: 0,
+            peeked: PeekBuffer::new(),
+            eof: false,
+            empty_reads: 0,
+            empty_read_policy: None,
+            separator: None,
+            separating: None,
+            separator_len: 0,
+            pool: Arc::new(BufferPool::new(pool::DEFAULT_SIZE, pool::IN_USE)),
+            memory_limit: None,
+            stop: Stop::new(),
+            entry_offset: None,
+        }
+    }
+
+    /// Starts configuring a chain over `readers`.
+    pub fn builder<T>(readers: T) -> MultiReaderBuilder<I>
+        where T: IntoIterator<Item = R, IntoIter = I>
+    {
+        MultiReaderBuilder::new(readers)
+    }
+
+    /// Index of the source the last read was served from. Once the chain is
+    /// exhausted it equals the number of sources.
+    pub fn source_index(&self) -> usize {
+        self.served
+    }
+
+    /// Number of bytes of that source consumed so far, i.e. the offset
+    /// within it of the next byte to be read.
+    pub fn source_offset(&self) -> u64 {
+        self.served_offset
+    }
+
+    /// Whether the chain has reached its end.
+    pub fn is_finished(&self) -> bool {
+        self.current.is_none() && self.peeked.is_empty()
+    }
+
+    /// Reads ahead until at least `n` bytes are buffered or the chain ends,
+    /// and returns everything buffered. The bytes are still returned by
+    /// subsequent reads, and may span several sources.
+    ///
+    /// Fails with `OutOfMemory` without reading anything if buffering `n`
+    /// bytes would go over the `memory_limit`.
+    pub fn fill_peek_buffer(&mut self, n: usize) -> io::Result<&[u8]> {
+        if let Some(limit) = self.memory_limit {
+            let reserved = self.pool.max_bytes() + 2 * self.separator_len;
+            if n > limit.saturating_sub(reserved) {
+                let msg = format!("buffering {} bytes would go over the memory limit of {} bytes",
+                                  n,
+                                  limit);
+                return Err(io::Error::new(io::ErrorKind::OutOfMemory, msg));
+            }
+        }
+        let mut chunk = self.pool.take();
+        while self.peeked.len() < n {
+            let want = ::std::cmp::min(chunk.len(), n - self.peeked.len());
+            let got = self.read_source(&mut chunk[..want])?;
+            if got == 0 {
+                break;
+            }
+            let separator = self.separating.is_some();
+            self.peeked.push(&chunk[..got], self.index, self.offset, separator);
+        }
+        self.pool.put(chunk);
+        Ok(self.peeked.as_slice())
+    }
+
+    /// Copies upcoming bytes into `buf` without consuming them. Fewer bytes
+    /// than `buf.len()` are returned only at the end of the chain.
+    pub fn peek(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let peeked = self.fill_peek_buffer(buf.len())?;
+        let n = ::std::cmp::min(buf.len(), peeked.len());
+        buf[..n].copy_from_slice(&peeked[..n]);
+        Ok(n)
+    }
+
+    /// Reads up to and including the next `byte`, appending to `buf`, like
+    /// `BufRead::read_until`. Returns the number of bytes read, 0 at the end
+    /// of the chain.
+    pub fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let mut total = 0;
+        loop {
+            let (want, found) = {
+                let available = match self.buffered() {
+                    Ok(available) => available,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                match available.iter().position(|&b| b == byte) {
+                    Some(i) => (i + 1, true),
+                    None => (available.len(), false),
+                }
+            };
+            if want == 0 {
+                self.eof = self.current.is_none();
+                return Ok(total);
+            }
+            let start = buf.len();
+            buf.resize(start + want, 0);
+            // Reads from the peek buffer stop at source boundaries.
+            let n = match self.read(&mut buf[start..]) {
+                Ok(n) => n,
+                Err(e) => {
+                    buf.truncate(start);
+                    return Err(e);
+                }
+            };
+            buf.truncate(start + n);
+            total += n;
+            // Nothing is read from a non-empty peek buffer once the chain
+            // has been shut down.
+            if n == 0 || (found && n == want) {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Reads up to and including the next newline, appending to `buf`, like
+    /// `BufRead::read_line`.
+    pub fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut line = Vec::new();
+        let n = self.read_until(b'\n', &mut line)?;
+        match String::from_utf8(line) {
+            Ok(line) => {
+                buf.push_str(&line);
+                Ok(n)
+            }
+            Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                         "stream did not contain valid UTF-8")),
+        }
+    }
+
+    /// Discards the next `n` bytes of the combined output, across source
+    /// boundaries if needed. Returns the number of bytes skipped, fewer than
+    /// `n` only at the end of the chain.
+    pub fn skip_bytes(&mut self, n: u64) -> io::Result<u64> {
+        let mut scratch = self.pool.take();
+        let mut skipped = 0;
+        while skipped < n {
+            let want = ::std::cmp::min(scratch.len() as u64, n - skipped) as usize;
+            match self.read(&mut scratch[..want]) {
+                Ok(0) => break,
+                Ok(got) => skipped += got as u64,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.pool.put(scratch);
+        Ok(skipped)
+    }
+
+    /// Returns the peek buffer, reading into it first if it is empty.
+    fn buffered(&mut self) -> io::Result<&[u8]> {
+        if self.peeked.is_empty() {
+            let mut chunk = self.pool.take();
+            let got = self.read_source(&mut chunk)?;
+            if got > 0 {
+                let separator = self.separating.is_some();
+                self.peeked.push(&chunk[..got], self.index, self.offset, separator);
+            }
+            self.pool.put(chunk);
+        }
+        Ok(self.peeked.as_slice())
+    }
+
+    /// Decides what an `Ok(0)` from a source means. `policy` gets the index
+    /// of the source and the number of consecutive empty reads from it so
+    /// far, starting at 1. By default every empty read ends the source.
+    pub fn empty_read_policy<F>(mut self, policy: F) -> MultiReader<R, I>
+        where F: FnMut(usize, usize) -> EmptyRead + Send + 'static
+    {
+        self.empty_read_policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Inserts `bytes` between every two sources.
+    pub fn separator(self, bytes: &[u8]) -> MultiReader<R, I> {
+        let len = bytes.len();
+        let bytes = bytes.to_vec();
+        let mut m = self.separator_with(move |_| io::Cursor::new(bytes.clone()));
+        m.separator_len = len;
+        m
+    }
+
+    /// Inserts the output of a reader made by `f` between every two sources.
+    /// `f` gets the index of the source about to start, and its reader is
+    /// read to the end before that source. Its bytes count as part of that
+    /// source for `source_index`, but not for `source_offset`.
+    pub fn separator_with<S, F>(mut self, mut f: F) -> MultiReader<R, I>
+        where S: Read + Send + 'static,
+              F: FnMut(usize) -> S + Send + 'static
+    {
+        self.separator = Some(Box::new(move |index| Box::new(f(index))));
+        self.separator_len = 0;
+        self
+    }
+
+    /// Sets the size of the scratch buffers used by `peek`, `read_line`,
+    /// `skip_bytes` and the like, 8 KiB by default. The buffers are kept
+    /// and reused for the whole life of the chain.
+    pub fn buffer_size(mut self, size: usize) -> MultiReader<R, I> {
+        self.pool = Arc::new(BufferPool::new(size, pool::IN_USE));
+        self
+    }
+
+    /// Takes scratch buffers from `pool`, which other chains may share,
+    /// instead of a pool of its own. This replaces `buffer_size`.
+    pub fn buffer_pool(mut self, pool: Arc<BufferPool>) -> MultiReader<R, I> {
+        self.pool = pool;
+        self
+    }
+
+    /// Bounds the memory the chain allocates for its own buffers: the peek
+    /// buffer, the scratch buffers and the copies of a `separator`. Peeking
+    /// further than the rest of the limit fails, see `fill_peek_buffer`,
+    /// while the other helpers only ever buffer one scratch buffer's worth.
+    /// Separators made by `separator_with` are left to the caller to bound.
+    /// Unlimited by default.
+    ///
+    /// Panics if the limit cannot even hold the scratch buffers and the
+    /// separator.
+    pub fn memory_limit(mut self, bytes: usize) -> MultiReader<R, I> {
+        let reserved = self.pool.max_bytes() + 2 * self.separator_len;
+        assert!(bytes >= reserved,
+                "a memory limit of {} bytes is below the {} bytes of scratch buffers and separator",
+                bytes,
+                reserved);
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// A handle for shutting the chain down from another thread, see the
+    /// `shutdown` module.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.stop.handle()
+    }
+
+    /// Lets the read in progress, if any, complete, and ends the chain
+    /// there: from then on reads return `Ok(0)`, or fail if a
+    /// `shutdown_error` is set.
+    pub fn shutdown(&self) {
+        self.stop.handle().shutdown();
+    }
+
+    /// Lets the source being read drain, and ends the chain at its end,
+    /// before the next source is opened.
+    pub fn stop_after_current_source(&self) {
+        self.stop.handle().stop_after_current_source();
+    }
+
+    /// Makes reads after a shutdown fail with an error of `kind` instead of
+    /// returning `Ok(0)`, so that consumers cannot mistake it for the end
+    /// of the data.
+    pub fn shutdown_error(mut self, kind: io::ErrorKind) -> MultiReader<R, I> {
+        self.stop.error = Some(kind);
+        self
+    }
+
+    /// Where the chain stopped after a shutdown, as the `source_index` and
+    /// `source_offset` of the last read before it, to resume from. `None`
+    /// until a read has seen the shutdown.
+    pub fn stopped_at(&self) -> Option<(usize, u64)> {
+        self.stop.stopped_at
+    }
+
+    /// Polls the iterator again after the chain has finished, so that
+    /// sources it yields from now on are read too. Returns whether there was
+    /// such a source.
+    pub fn rearm(&mut self) -> bool {
+        if self.current.is_none() {
+            self.current = self.readers.next();
+            self.eof = false;
+            if self.index > 0 {
+                self.separate();
+            }
+        }
+        self.current.is_some()
+    }
+
+    /// A chain over the rest of the current source and the `n - 1` sources
+    /// after it, which this chain goes on from once the batch is dropped.
+    /// This way an endless iterator of sources can be read in batches. The
+    /// batch is a plain chain: separators and the like of this one are not
+    /// applied to it.
+    ///
+    /// Panics if bytes have been peeked at, since they may belong to sources
+    /// past the batch.
+    pub fn take_sources(&mut self, n: usize) -> MultiReader<R, TakeSources<'_, R, I>> {
+        assert!(self.peeked.is_empty(), "cannot take sources while bytes are peeked at");
+        self.separating = None;
+        let entry_offset = Arc::new(AtomicU64::new(0));
+        MultiReader::new(TakeSources {
+                chain: self,
+                left: n,
+                started: false,
+                exhausted: false,
+                entry_offset: entry_offset.clone(),
+            })
+            .entering_at(entry_offset)
+    }
+
+    /// Hands over the unread rest of the chain, from the next byte on, such
+    /// as to another worker once a header has been parsed. Bytes already
+    /// peeked at are read first. The rest keeps the settings of this chain,
+    /// but its `source_index` and `source_offset` count from the split, and
+    /// it is shut down separately.
+    pub fn split_off(mut self) -> MultiReader<R, I> {
+        let (base, base_offset) = (self.served, self.served_offset);
+        let rebase = |index: usize, offset: u64| {
+            let offset = if index == base { offset - base_offset } else { offset };
+            (index - base, offset)
+        };
+        let (index, offset) = rebase(self.index, self.offset);
+        self.index = index;
+        self.offset = offset;
+        for run in self.peeked.runs.iter_mut() {
+            let (index, offset) = rebase(run.1, run.2);
+            *run = (run.0, index, offset, run.3);
+        }
+        self.served = 0;
+        self.served_offset = 0;
+        self.stop = self.stop.detached();
+        self
+    }
+
+    /// Wraps every source with `f` as it is entered. `f` gets the index of
+    /// the source along with the source itself. Bytes that have already been
+    /// peeked at are not passed through `f`.
+    pub fn map_each<W, F>(self, mut f: F) -> MultiReader<W, MapEach<I, F>>
+        where W: Read,
+              F: FnMut(usize, R) -> W
+    {
+        let index = self.index;
+        self.with_sources(move |readers, current| {
+            let current = current.map(|r| f(index, r));
+            let readers = MapEach {
+                readers,
+                index: index + 1,
+                f,
+            };
+            (readers, current)
+        })
+    }
+
+    /// Appends `sources`, to be read once the chain's own sources are done.
+    /// Everything else, like the position and the policies, carries over.
+    /// Pass `Some(reader)` to append a single reader, or the `into_sources`
+    /// of another chain to append that chain.
+    pub fn chain<T>(self, sources: T) -> MultiReader<R, iter::Chain<I, T::IntoIter>>
+        where T: IntoIterator<Item = R>
+    {
+        let eof = self.eof;
+        self.with_sources(move |readers, mut current| {
+            let mut readers = readers.chain(sources);
+            if current.is_none() && !eof {
+                current = readers.next();
+            }
+            (readers, current)
+        })
+    }
+
+    /// Drops empty sources, so that they are not entered at all, see the
+    /// `empty` module.
+    pub fn skip_empty(self) -> MultiReader<Probed<R>, SkipEmpty<I>> {
+        self.with_sources(|readers, current| {
+            let mut readers = SkipEmpty::new(readers);
+            let current = current.and_then(Probed::probe).or_else(|| readers.next());
+            (readers, current)
+        })
+    }
+
+    /// Replaces the sources of the chain with what `f` makes of them: the
+    /// sources left and the current one. Everything else carries over.
+    fn with_sources<S, J, F>(self, f: F) -> MultiReader<S, J>
+        where F: FnOnce(I, Option<R>) -> (J, Option<S>)
+    {
+        let (readers, current) = f(self.readers, self.current);
+        MultiReader {
+            readers,
+            current,
+            index: self.index,
+            offset: self.offset,
+            served: self.served,
+            served_offset: self.served_offset,
+            peeked: self.peeked,
+            eof: self.eof,
+            empty_reads: self.empty_reads,
+            empty_read_policy: self.empty_read_policy,
+            separator: self.separator,
+            separating: self.separating,
+            separator_len: self.separator_len,
+            pool: self.pool,
+            memory_limit: self.memory_limit,
+            stop: self.stop,
+            entry_offset: self.entry_offset,
+        }
+    }
+
+    /// Drops every byte of the combined output for which `predicate`
+    /// returns `false`.
+    pub fn filter_bytes<P>(self, predicate: P) -> FilterBytes<MultiReader<R, I>, P>
+        where P: FnMut(u8) -> bool
+    {
+        FilterBytes {
+            inner: self,
+            predicate,
+        }
+    }
+
+    /// Writes everything read from the chain to `sink` too, see `Tee`.
+    pub fn tee<W: Write>(self, sink: W) -> Tee<MultiReader<R, I>, W> {
+        Tee::new(self, sink)
+    }
+
+    /// Fails reads once a source is found not to be made of whole records
+    /// of `size` bytes, see `Aligned`.
+    pub fn record_size(self, size: usize) -> Aligned<R, I> {
+        Aligned::new(self, size)
+    }
+
+    /// Fails reads at the first byte that is not valid UTF-8, see
+    /// `ValidateUtf8`.
+    pub fn validate_utf8(self) -> ValidateUtf8<R, I> {
+        ValidateUtf8::new(self)
+    }
+
+    /// Decodes the chain as UTF-8, chars split between sources included,
+    /// see `Chars`.
+    pub fn chars(self) -> Chars<R, I> {
+        Chars::new(self)
+    }
+
+    /// Calls `f` with every chunk read from the chain and the index of the
+    /// source it came from.
+    pub fn inspect<F>(self, f: F) -> Inspect<R, I, F>
+        where F: FnMut(usize, &[u8])
+    {
+        Inspect::new(self, f)
+    }
+
+    /// Calls `f` with the index of the source being read and the number of
+    /// bytes read so far whenever a read returns at least `interval` after
+    /// the previous call, see `Heartbeat`.
+    pub fn heartbeat<F>(self, interval: Duration, f: F) -> Heartbeat<R, I, F>
+        where F: FnMut(usize, u64)
+    {
+        Heartbeat::new(self, interval, f)
+    }
+
+    /// Splits the combined output into blocks of exactly `size` bytes,
+    /// except for a shorter last one.
+    pub fn into_chunks(self, size: usize) -> Chunks<MultiReader<R, I>> {
+        assert!(size > 0, "chunks must not be empty");
+        Chunks {
+            inner: self,
+            size,
+            chunk: Vec::new(),
+        }
+    }
+
+    /// Splits the combined output into records terminated by `delimiter`,
+    /// which may straddle two sources. A trailing delimiter does not start
+    /// an empty last record.
+    pub fn records(self, delimiter: &[u8]) -> Records<MultiReader<R, I>> {
+        assert!(!delimiter.is_empty(), "the delimiter must not be empty");
+        Records {
+            inner: self,
+            delimiter: delimiter.to_vec(),
+            buf: Vec::new(),
+            scanned: 0,
+            done: false,
         }
     }
 }
 
-impl<R: Read, I: Iterator<Item = R>> Read for MultiReader<R, I> {
+/// Bytes read ahead by `peek`, along with the source each run of them came
+/// from.
+struct PeekBuffer {
+    data: Vec<u8>,
+    pos: usize,
+    // (end position in `data`, source index, source offset at the end,
+    // whether the bytes are a separator). Separator bytes are reported at
+    // offset 0 of the source they come before, as `read` does.
+    runs: VecDeque<(usize, usize, u64, bool)>,
+}
+
+impl PeekBuffer {
+    fn new() -> PeekBuffer {
+        PeekBuffer {
+            data: Vec::new(),
+            pos: 0,
+            runs: VecDeque::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data[self.pos..]
+    }
+
+    fn push(&mut self, bytes: &[u8], index: usize, offset: u64, separator: bool) {
+        self.data.extend_from_slice(bytes);
+        self.runs.push_back((self.data.len(), index, offset, separator));
+    }
+
+    /// Index of the source of the next buffered byte.
+    fn front_index(&self) -> Option<usize> {
+        self.runs.front().map(|&(_, index, _, _)| index)
+    }
+
+    /// Serves buffered bytes of a single source, returning how many were
+    /// copied, the index of their source and the offset within it past them.
+    fn read(&mut self, buf: &mut [u8]) -> Option<(usize, usize, u64)> {
+        let (end, index, offset, separator) = *self.runs.front()?;
+        let n = ::std::cmp::min(buf.len(), end - self.pos);
+        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        let served_offset = if separator { offset } else { offset - (end - self.pos) as u64 };
+        if self.pos == end {
+            self.runs.pop_front();
+        }
+        if self.runs.is_empty() {
+            self.data.clear();
+            self.pos = 0;
+        }
+        Some((n, index, served_offset))
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R> + Clone> MultiReader<R, iter::Cycle<I>> {
+    /// Chains `sources` over and over, forever. Reads never return if none
+    /// of the sources has any data.
+    pub fn cycle<T>(sources: T) -> MultiReader<R, iter::Cycle<I>>
+        where T: IntoIterator<Item = R, IntoIter = I>
+    {
+        MultiReader::new(sources.into_iter().cycle())
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R> + Clone> MultiReader<R, Repeat<I>> {
+    /// Chains `sources` `times` times in a row.
+    pub fn repeat<T>(sources: T, times: usize) -> MultiReader<R, Repeat<I>>
+        where T: IntoIterator<Item = R, IntoIter = I>
+    {
+        MultiReader::new(iter::repeat_n(sources.into_iter(), times).flatten())
+    }
+}
+
+impl<R: Read> MultiReader<R, vec::IntoIter<R>> {
+    /// Collects `sources` and chains them in a random order, the same one
+    /// for the same `seed`.
+    pub fn shuffled<T>(sources: T, seed: u64) -> MultiReader<R, vec::IntoIter<R>>
+        where T: IntoIterator<Item = R>
+    {
+        let mut sources: Vec<R> = sources.into_iter().collect();
+        let mut rng = XorShift::new(seed);
+        for i in (1..sources.len()).rev() {
+            let j = rng.below(i as u64 + 1) as usize;
+            sources.swap(i, j);
+        }
+        MultiReader::new(sources.into_iter())
+    }
+}
+
+/// Source iterator of `MultiReader::repeat`.
+pub type Repeat<I> = iter::Flatten<iter::RepeatN<I>>;
+
+impl<R: Read, I: ExactSizeIterator<Item = R>> MultiReader<R, I> {
+    /// Number of sources not finished yet, the current one included.
+    pub fn remaining_sources(&self) -> usize {
+        self.readers.len() + self.current.is_some() as usize
+    }
+
+    /// Number of sources in the chain, finished ones included.
+    pub fn total_sources(&self) -> usize {
+        self.index + self.remaining_sources()
+    }
+}
+
+/// Source iterator of a growable chain, see `MultiReader::growable`.
+pub struct Queue<R> {
+    readers: VecDeque<R>,
+}
+
+impl<R> Iterator for Queue<R> {
+    type Item = R;
+
+    fn next(&mut self) -> Option<R> {
+        self.readers.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.readers.len(), Some(self.readers.len()))
+    }
+}
+
+impl<R> ExactSizeIterator for Queue<R> {}
+
+impl<R: Read> MultiReader<R, Queue<R>> {
+    /// Creates an empty chain that sources can be pushed to.
+    pub fn growable() -> MultiReader<R, Queue<R>> {
+        MultiReader::new(Queue { readers: VecDeque::new() })
+    }
+
+    /// Appends a source. A chain that already returned EOF stays finished
+    /// until `rearm` is called.
+    pub fn push(&mut self, reader: R) {
+        self.readers.readers.push_back(reader);
+        if self.current.is_none() && !self.eof {
+            self.current = self.readers.next();
+        }
+    }
+
+    /// Number of pushed sources not entered yet.
+    pub fn pending(&self) -> usize {
+        self.readers.readers.len()
+    }
+}
+
+/// Iterator behind `MultiReader::map_each`.
+pub struct MapEach<I, F> {
+    readers: I,
+    index: usize,
+    f: F,
+}
+
+impl<R, W, I, F> Iterator for MapEach<I, F>
+    where I: Iterator<Item = R>,
+          F: FnMut(usize, R) -> W
+{
+    type Item = W;
+
+    fn next(&mut self) -> Option<W> {
+        let r = self.readers.next()?;
+        let w = (self.f)(self.index, r);
+        self.index += 1;
+        Some(w)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.readers.size_hint()
+    }
+}
+
+impl<R, W, I, F> ExactSizeIterator for MapEach<I, F>
+    where I: ExactSizeIterator<Item = R>,
+          F: FnMut(usize, R) -> W
+{
+}
+
+/// Iterator behind `MultiReader::take_sources`.
+pub struct TakeSources<'a, R: Read + 'a, I: Iterator<Item = R> + 'a> {
+    chain: &'a mut MultiReader<R, I>,
+    left: usize,
+    started: bool,
+    exhausted: bool,
+    // Offset of the source taken last, for the batch.
+    entry_offset: Arc<AtomicU64>,
+}
+
+impl<'a, R: Read, I: Iterator<Item = R>> Iterator for TakeSources<'a, R, I> {
+    type Item = R;
+
+    fn next(&mut self) -> Option<R> {
+        if self.left == 0 {
+            return None;
+        }
+        self.left -= 1;
+        if !self.started {
+            let first = self.chain.current.take();
+            self.started = first.is_some();
+            self.entry_offset.store(self.chain.offset, Ordering::SeqCst);
+            return first;
+        }
+        let r = self.chain.readers.next();
+        match r {
+            Some(_) => {
+                self.chain.index += 1;
+                self.chain.offset = 0;
+                self.entry_offset.store(self.chain.entry_offset(), Ordering::SeqCst);
+            }
+            None => self.exhausted = true,
+        }
+        r
+    }
+}
+
+impl<'a, R: Read, I: Iterator<Item = R>> Drop for TakeSources<'a, R, I> {
+    fn drop(&mut self) {
+        let chain = &mut *self.chain;
+        if !self.started {
+            return;
+        }
+        // The last source of the batch was taken: move on past it.
+        chain.index += 1;
+        if !self.exhausted {
+            chain.current = chain.readers.next();
+        }
+        chain.offset = chain.entry_offset();
+        chain.served = chain.index;
+        chain.served_offset = chain.offset;
+    }
+}
+
+/// Reader behind `MultiReader::filter_bytes`.
+pub struct FilterBytes<R, P> {
+    inner: R,
+    predicate: P,
+}
+
+impl<R, P> FilterBytes<R, P> {
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, P: FnMut(u8) -> bool> Read for FilterBytes<R, P> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         loop {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut kept = 0;
+            for i in 0..n {
+                if (self.predicate)(buf[i]) {
+                    buf[kept] = buf[i];
+                    kept += 1;
+                }
+            }
+            // Everything read may have been filtered out, which is not EOF.
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+/// Iterator behind `MultiReader::into_chunks`.
+///
+/// A failed read yields the error; the bytes of the chunk read so far are
+/// kept, and iterating further resumes filling it.
+pub struct Chunks<R> {
+    inner: R,
+    size: usize,
+    chunk: Vec<u8>,
+}
+
+impl<R> Chunks<R> {
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Iterator for Chunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        let mut filled = self.chunk.len();
+        self.chunk.resize(self.size, 0);
+        while filled < self.size {
+            match self.inner.read(&mut self.chunk[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.chunk.truncate(filled);
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.chunk.truncate(filled);
+        if filled == 0 {
+            return None;
+        }
+        Some(Ok(::std::mem::take(&mut self.chunk)))
+    }
+}
+
+/// Iterator behind `MultiReader::records`.
+pub struct Records<R> {
+    inner: R,
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    // Length of the prefix of `buf` known not to contain the delimiter.
+    scanned: usize,
+    done: bool,
+}
+
+impl<R> Records<R> {
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn split(&mut self) -> Option<Vec<u8>> {
+        let d = self.delimiter.len();
+        let start = self.scanned.saturating_sub(d - 1);
+        match self.buf[start..].windows(d).position(|w| w == &self.delimiter[..]) {
+            Some(i) => {
+                let rest = self.buf.split_off(start + i + d);
+                let mut record = ::std::mem::replace(&mut self.buf, rest);
+                record.truncate(start + i);
+                self.scanned = 0;
+                Some(record)
+            }
+            None => {
+                self.scanned = self.buf.len();
+                None
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Records<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        let mut chunk = [0; 8 * 1024];
+        loop {
+            if let Some(record) = self.split() {
+                return Some(Ok(record));
+            }
+            if self.done {
+                if self.buf.is_empty() {
+                    return None;
+                }
+                self.scanned = 0;
+                return Some(Ok(::std::mem::take(&mut self.buf)));
+            }
+            match self.inner.read(&mut chunk) {
+                Ok(0) => self.done = true,
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// A source that may have failed to open. Every read of a failed source
+/// returns its error, so the chain does not go on past missing data. An
+/// `Interrupted` error is returned as `Other`, as it would be retried.
+pub struct Fallible<R> {
+    inner: Result<R, Failure>,
+}
+
+struct Failure {
+    // The original error, returned by the first read.
+    error: Option<io::Error>,
+    kind: io::ErrorKind,
+    message: String,
+}
+
+impl<R: Read> Fallible<R> {
+    pub fn is_ok(&self) -> bool {
+        self.inner.is_ok()
+    }
+
+    pub fn get_ref(&self) -> Option<&R> {
+        self.inner.as_ref().ok()
+    }
+}
+
+impl<R: Read> From<io::Result<R>> for Fallible<R> {
+    fn from(result: io::Result<R>) -> Fallible<R> {
+        let inner = result.map_err(|e| {
+            let e = not_interrupted(e);
+            Failure {
+                kind: e.kind(),
+                message: e.to_string(),
+                error: Some(e),
+            }
+        });
+        Fallible { inner }
+    }
+}
+
+impl<R: Read> Read for Fallible<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner {
+            Ok(ref mut r) => r.read(buf),
+            Err(ref mut f) => {
+                Err(f.error.take().unwrap_or_else(|| io::Error::new(f.kind, f.message.clone())))
+            }
+        }
+    }
+}
+
+/// Turns an `Interrupted` error into an `Other` one, for errors returned by
+/// reads that would otherwise be retried as is, like `read_to_end` does.
+pub(crate) fn not_interrupted(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::Interrupted {
+        return io::Error::other(e);
+    }
+    e
+}
+
+impl<R: Read, I: Iterator<Item = R>> MultiReader<R, I> {
+    fn read_source(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(stopped) = self.stopped() {
+            return stopped;
+        }
+        loop {
+            if let Some(ref mut separator) = self.separating {
+                let n = separator.read(buf)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+            }
+            self.separating = None;
             match self.current {
                 Some(ref mut r) => {
-                    let n = try!(r.read(buf));
+                    let n = r.read(buf)?;
                     if n > 0 {
+                        self.empty_reads = 0;
+                        self.offset += n as u64;
                         return Ok(n);
                     }
+                    self.empty_reads += 1;
+                    let action = match self.empty_read_policy {
+                        Some(ref mut policy) => policy(self.index, self.empty_reads),
+                        None => EmptyRead::Advance,
+                    };
+                    match action {
+                        EmptyRead::Advance => self.empty_reads = 0,
+                        EmptyRead::Retry => continue,
+                        EmptyRead::Return => return Ok(0),
+                    }
                 }
                 None => return Ok(0),
             }
+            if self.stop.at_boundary() {
+                self.stop.handle().shutdown();
+                self.served = self.index;
+                self.served_offset = self.offset;
+                return self.stopped().unwrap();
+            }
+            // Closes the exhausted source before the next one is opened.
+            self.current = None;
             self.current = self.readers.next();
+            self.index += 1;
+            self.offset = self.entry_offset();
+            self.separate();
+        }
+    }
+
+    /// Has the chain take the offset every source is entered at, starting
+    /// with the current one, from `entry_offset`.
+    pub(crate) fn entering_at(mut self, entry_offset: Arc<AtomicU64>) -> MultiReader<R, I> {
+        self.offset = entry_offset.swap(0, Ordering::SeqCst);
+        self.served_offset = self.offset;
+        self.entry_offset = Some(entry_offset);
+        self
+    }
+
+    /// Offset within the source just entered at which reading starts.
+    fn entry_offset(&self) -> u64 {
+        self.entry_offset.as_ref().map_or(0, |offset| offset.swap(0, Ordering::SeqCst))
+    }
+
+    /// What reads return once the chain has been shut down.
+    fn stopped(&mut self) -> Option<io::Result<usize>> {
+        if !self.stop.is_set() {
+            return None;
+        }
+        if self.stop.stopped_at.is_none() {
+            self.stop.stopped_at = Some((self.served, self.served_offset));
+        }
+        Some(match self.stop.error {
+            Some(kind) => Err(io::Error::new(kind, "chain shut down")),
+            None => Ok(0),
+        })
+    }
+
+    /// Starts the separator before the source just entered, if any.
+    fn separate(&mut self) {
+        if self.current.is_some() {
+            if let Some(ref mut separator) = self.separator {
+                self.separating = Some(separator(self.index));
+            }
         }
     }
 }
 
+impl<R: Read + Seek, I: Iterator<Item = R>> MultiReader<R, I> {
+    /// Like `skip_bytes`, but seeks past the skipped bytes of every source
+    /// instead of reading them.
+    pub fn skip_bytes_seeking(&mut self, n: u64) -> io::Result<u64> {
+        let mut skipped = 0;
+        while skipped < n {
+            // Peeked bytes have already been read from the sources, and
+            // separators cannot seek.
+            if !self.peeked.is_empty() || self.separating.is_some() {
+                let mut scratch = self.pool.take();
+                let want = ::std::cmp::min(scratch.len() as u64, n - skipped) as usize;
+                let got = self.read(&mut scratch[..want])?;
+                self.pool.put(scratch);
+                if got == 0 {
+                    break;
+                }
+                skipped += got as u64;
+                continue;
+            }
+            let step = match self.current {
+                Some(ref mut r) => {
+                    let pos = r.stream_position()?;
+                    let end = r.seek(SeekFrom::End(0))?;
+                    let step = ::std::cmp::min(end.saturating_sub(pos), n - skipped);
+                    r.seek(SeekFrom::Start(pos + step))?;
+                    step
+                }
+                None => break,
+            };
+            self.offset += step;
+            skipped += step;
+            self.served = self.index;
+            self.served_offset = self.offset;
+            if step == 0 {
+                // At the end of the source: let a read move on to the next.
+                let got = self.read(&mut [0])?;
+                if got == 0 {
+                    break;
+                }
+                skipped += got as u64;
+            }
+        }
+        Ok(skipped)
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Read for MultiReader<R, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(stopped) = self.stopped() {
+            return stopped;
+        }
+        if self.stop.at_boundary() && self.peeked.front_index().is_some_and(|i| i != self.served) {
+            self.stop.handle().shutdown();
+            return self.stopped().unwrap();
+        }
+        if let Some((n, index, offset)) = self.peeked.read(buf) {
+            self.served = index;
+            self.served_offset = offset;
+            return Ok(n);
+        }
+        let n = self.read_source(buf)?;
+        self.served = self.index;
+        self.served_offset = self.offset;
+        if n == 0 && self.current.is_none() {
+            self.eof = true;
+        }
+        Ok(n)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io;
-    use std::io::Read;
-    use super::MultiReader;
+    use std::io::{Cursor, Read};
+    use super::{EmptyRead, Fallible, MultiReader};
+    use flatten::Sources;
+    use testing::MaybeErrReader;
 
     #[test]
     fn test_single_reader() {
@@ -91,54 +1241,557 @@ mod tests {
     }
 
     #[test]
-    fn test_first_readers_is_empty() {}
+    fn test_source_index() {
+        let sources = vec![&b"ab"[..], &b""[..], &b"c"[..]];
+        let mut m = MultiReader::new(sources.into_iter());
+        assert_eq!(m.source_index(), 0);
+        assert_eq!(m.read(&mut [0; 10]).unwrap(), 2);
+        assert_eq!(m.source_index(), 0);
+        assert_eq!(m.read(&mut [0; 10]).unwrap(), 1);
+        assert_eq!(m.source_index(), 2);
+        assert_eq!(m.read(&mut [0; 10]).unwrap(), 0);
+        assert_eq!(m.source_index(), 3);
+    }
 
     #[test]
-    fn test_middle_readers_is_empty() {}
+    fn test_into_chunks() {
+        let sources = vec![&b"abc"[..], &b""[..], &b"defg"[..], &b"h"[..]];
+        let chunks: Vec<Vec<u8>> = MultiReader::new(sources.into_iter())
+            .into_chunks(3)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(chunks, vec![b"abc".to_vec(), b"def".to_vec(), b"gh".to_vec()]);
+    }
 
     #[test]
-    fn test_last_readers_is_empty() {}
+    fn test_into_chunks_error() {
+        let sources: Vec<Box<dyn Read>> = vec![Box::new(&b"ab"[..]),
+                                               Box::new(MaybeErrReader::broken(&b"cd"[..], 0))];
+        let mut chunks = MultiReader::new(sources.into_iter()).into_chunks(3);
+        assert!(chunks.next().unwrap().is_err());
+        assert_eq!(chunks.next().unwrap().unwrap(), b"abc");
+        assert_eq!(chunks.next().unwrap().unwrap(), b"d");
+        assert!(chunks.next().is_none());
+    }
 
     #[test]
-    fn test_only_empty_readers() {}
+    fn test_records() {
+        let sources = vec![&b"a<>b<"[..], &b">"[..], &b"<>c<"[..], &b"<>"[..]];
+        let records: Vec<Vec<u8>> = MultiReader::new(sources.into_iter())
+            .records(b"<>")
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(records, vec![b"a".to_vec(), b"b".to_vec(), b"".to_vec(), b"c<".to_vec()]);
+
+        let sources = vec![&b"x\0y"[..]];
+        let mut records = MultiReader::new(sources.into_iter()).records(b"\0");
+        assert_eq!(records.next().unwrap().unwrap(), b"x");
+        assert_eq!(records.next().unwrap().unwrap(), b"y");
+        assert!(records.next().is_none());
+    }
 
     #[test]
-    fn test_err_during_read() {
-        struct MaybeErrReader<R> {
-            reader: R,
-            read_no: i32,
-            fail_at: i32,
-        }
-
-        impl<R: Read> MaybeErrReader<R> {
-            fn good(reader: R) -> MaybeErrReader<R> {
-                MaybeErrReader {
-                    reader: reader,
-                    read_no: 0,
-                    fail_at: -1,
-                }
+    fn test_read_line() {
+        let sources = vec![&b"one\ntw"[..], &b"o\n"[..], &b"three"[..]];
+        let mut m = MultiReader::new(sources.into_iter());
+        let mut line = String::new();
+        assert_eq!(m.read_line(&mut line).unwrap(), 4);
+        assert_eq!(line, "one\n");
+        line.clear();
+        assert_eq!(m.read_line(&mut line).unwrap(), 4);
+        assert_eq!(line, "two\n");
+        assert_eq!(m.source_index(), 1);
+        line.clear();
+        assert_eq!(m.read_line(&mut line).unwrap(), 5);
+        assert_eq!(line, "three");
+        assert_eq!(m.read_line(&mut line).unwrap(), 0);
+        assert!(m.is_finished());
+    }
+
+    #[test]
+    fn test_read_until() {
+        let sources = vec![&b"a,b"[..], &b",c"[..]];
+        let mut m = MultiReader::new(sources.into_iter());
+        let mut buf = Vec::new();
+        assert_eq!(m.read_until(b',', &mut buf).unwrap(), 2);
+        assert_eq!(m.read_until(b',', &mut buf).unwrap(), 2);
+        assert_eq!(buf, b"a,b,");
+        let mut rest = Vec::new();
+        m.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"c");
+    }
+
+    #[test]
+    fn test_skip_bytes() {
+        let sources = vec![&b"abc"[..], &b"defg"[..], &b"h"[..]];
+        let mut m = MultiReader::new(sources.into_iter());
+        assert_eq!(m.fill_peek_buffer(2).unwrap(), b"ab");
+        assert_eq!(m.skip_bytes(5).unwrap(), 5);
+        assert_eq!((m.source_index(), m.source_offset()), (1, 2));
+        assert_eq!(m.skip_bytes(10).unwrap(), 3);
+        assert_eq!(m.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_skip_bytes_seeking() {
+        let sources = vec![&b"abc"[..], &b""[..], &b"defg"[..], &b"h"[..]];
+        let mut m = MultiReader::new(sources.into_iter().map(io::Cursor::new));
+        assert_eq!(m.fill_peek_buffer(1).unwrap(), b"a");
+        assert_eq!(m.skip_bytes_seeking(5).unwrap(), 5);
+        assert_eq!((m.source_index(), m.source_offset()), (2, 2));
+        let mut rest = Vec::new();
+        m.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"fgh");
+        assert_eq!(m.skip_bytes_seeking(1).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_chain() {
+        let first = MultiReader::new(vec![&b"ab"[..], &b"c"[..]].into_iter());
+        let second = MultiReader::new(vec![&b"d"[..]].into_iter());
+        let mut m = first.chain(second.into_sources()).chain(Some(&b"e"[..]));
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abcde");
+        assert_eq!(m.source_index(), 4);
+
+        let mut empty = MultiReader::new(Vec::new().into_iter()).chain(Some(&b"x"[..]));
+        assert_eq!(empty.read(&mut [0; 4]).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_small_buffer_size() {
+        let sources = vec![&b"abcdef\n"[..], &b"gh"[..]];
+        let mut m = MultiReader::new(sources.into_iter()).buffer_size(2);
+        let mut line = String::new();
+        m.read_line(&mut line).unwrap();
+        assert_eq!(line, "abcdef\n");
+        assert_eq!(m.fill_peek_buffer(5).unwrap(), b"gh");
+        assert_eq!(m.skip_bytes(5).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let sources = vec![&b"abcdef"[..], &b"ghij"[..]];
+        // 2 scratch buffers of 2 bytes and 2 copies of the separator.
+        let mut m = MultiReader::new(sources.into_iter())
+            .buffer_size(2)
+            .separator(b"|")
+            .memory_limit(12);
+        assert_eq!(m.fill_peek_buffer(6).unwrap(), b"abcdef");
+        let err = m.fill_peek_buffer(7).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+        let mut line = Vec::new();
+        m.read_until(b'\n', &mut line).unwrap();
+        assert_eq!(line, b"abcdef|ghij");
+    }
+
+    #[test]
+    #[should_panic(expected = "below the 10 bytes")]
+    fn test_memory_limit_too_low() {
+        let m = MultiReader::new(vec![&b""[..]].into_iter()).buffer_size(4).separator(b"-");
+        let _ = m.memory_limit(9);
+    }
+
+    #[test]
+    fn test_split_off() {
+        let sources = vec![&b"HEAD\nbo"[..], &b"dy"[..], &b"!"[..]];
+        let mut m = MultiReader::new(sources.into_iter()).separator(b"|");
+        let mut header = Vec::new();
+        m.read_until(b'\n', &mut header).unwrap();
+        assert_eq!(header, b"HEAD\n");
+        m.shutdown();
+        let mut tail = m.split_off();
+        assert_eq!((tail.source_index(), tail.source_offset()), (0, 0));
+        let mut buf = [0; 2];
+        tail.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"bo");
+        assert_eq!((tail.source_index(), tail.source_offset()), (0, 2));
+        let mut rest = Vec::new();
+        tail.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"|dy|!");
+        assert_eq!(tail.source_index(), 3);
+    }
+
+    #[test]
+    fn test_take_sources() {
+        let sources = (0..).map(|i| Cursor::new(vec![b'a' + i as u8; 2]));
+        let mut m = MultiReader::new(sources);
+        let mut buf = [0; 1];
+        m.read_exact(&mut buf).unwrap();
+        let mut batch = Vec::new();
+        m.take_sources(3).read_to_end(&mut batch).unwrap();
+        assert_eq!(batch, b"abbcc");
+        assert_eq!((m.source_index(), m.source_offset()), (3, 0));
+        let mut batch = Vec::new();
+        m.take_sources(2).read_to_end(&mut batch).unwrap();
+        assert_eq!(batch, b"ddee");
+        assert_eq!(m.take_sources(0).read(&mut buf).unwrap(), 0);
+        m.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"f");
+
+        // Asking for more sources than are left.
+        let mut m = MultiReader::new(vec![&b"ab"[..], &b"c"[..]].into_iter());
+        let mut batch = Vec::new();
+        m.take_sources(5).read_to_end(&mut batch).unwrap();
+        assert_eq!(batch, b"abc");
+        assert!(m.is_finished());
+        assert_eq!(m.source_index(), 2);
+        assert_eq!(m.take_sources(1).read(&mut buf).unwrap(), 0);
+        assert_eq!(m.source_index(), 2);
+    }
+
+    #[test]
+    fn test_separators() {
+        let sources = vec![&b"a"[..], &b""[..], &b"b"[..]];
+        let mut m = MultiReader::new(sources.into_iter()).separator(b"--");
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"a----b");
+
+        let sources = vec![&b"x"[..], &b"y"[..]];
+        let mut m = MultiReader::new(sources.into_iter())
+            .separator_with(|i| io::Cursor::new(format!("<{}>", i)));
+        let mut buf = [0; 8];
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!(m.peek(&mut buf[..4]).unwrap(), 4);
+        assert_eq!(&buf[..4], b"<1>y");
+        assert_eq!(m.read(&mut buf).unwrap(), 3);
+        assert_eq!((m.source_index(), m.source_offset()), (1, 0));
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!((m.source_index(), m.source_offset()), (1, 1));
+    }
+
+    #[test]
+    fn test_peek_across_separators() {
+        let sources = vec![&b"x"[..], &b"yz"[..]];
+        let mut m = MultiReader::new(sources.into_iter()).separator(b"<1>");
+        let mut buf = [0; 4];
+        assert_eq!(m.read(&mut buf[..1]).unwrap(), 1);
+        assert_eq!(m.fill_peek_buffer(4).unwrap(), b"<1>y");
+        assert_eq!(m.read(&mut buf[..1]).unwrap(), 1);
+        assert_eq!((m.source_index(), m.source_offset()), (1, 0));
+        assert_eq!(m.read(&mut buf[..2]).unwrap(), 2);
+        assert_eq!(&buf[..2], b"1>");
+        assert_eq!((m.source_index(), m.source_offset()), (1, 0));
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!((m.source_index(), m.source_offset()), (1, 1));
+    }
+
+    #[test]
+    fn test_read_line_across_separators() {
+        let sources = vec![&b"one\ntwo"[..], &b"three\n"[..]];
+        let mut m = MultiReader::new(sources.into_iter()).separator(b"\n\n");
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if m.read_line(&mut line).unwrap() == 0 {
+                break;
             }
+            lines.push(line);
+        }
+        assert_eq!(lines, vec!["one\n", "two\n", "\n", "three\n"]);
+    }
+
+    #[test]
+    fn test_separators_after_rearm() {
+        let mut m = MultiReader::growable().separator(b",");
+        m.push(&b"a"[..]);
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        m.push(&b"b"[..]);
+        assert!(m.rearm());
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"a,b");
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut m = MultiReader::cycle(vec![&b"ab"[..], &b""[..], &b"c"[..]]);
+        let mut buf = [0; 8];
+        m.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"abcabcab");
+        assert_eq!(m.source_index(), 6);
+    }
 
-            fn broken(reader: R, fail_at: i32) -> MaybeErrReader<R> {
-                MaybeErrReader {
-                    reader: reader,
-                    read_no: 0,
-                    fail_at: fail_at,
+    #[test]
+    fn test_repeat() {
+        let mut out = Vec::new();
+        MultiReader::repeat(vec![&b"ab"[..], &b"c"[..]], 3).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abcabcabc");
+        out.clear();
+        MultiReader::repeat(vec![&b"ab"[..]], 0).read_to_end(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_shuffled() {
+        let read = |seed| {
+            let sources: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i]).collect();
+            let mut out = Vec::new();
+            MultiReader::shuffled(sources.into_iter().map(io::Cursor::new), seed)
+                .read_to_end(&mut out)
+                .unwrap();
+            out
+        };
+        let order = read(1);
+        assert_eq!(read(1), order);
+        assert_ne!(read(2), order);
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..20).collect::<Vec<u8>>());
+        assert_ne!(order, sorted);
+    }
+
+    #[test]
+    fn test_remaining_sources() {
+        let sources = vec![&b"ab"[..], &b""[..], &b"c"[..]];
+        let mut m = MultiReader::new(sources.into_iter()).map_each(|_, r| r);
+        assert_eq!((m.remaining_sources(), m.total_sources()), (3, 3));
+        assert_eq!(m.read(&mut [0; 4]).unwrap(), 2);
+        assert_eq!((m.remaining_sources(), m.total_sources()), (3, 3));
+        assert_eq!(m.read(&mut [0; 4]).unwrap(), 1);
+        assert_eq!((m.remaining_sources(), m.total_sources()), (1, 3));
+        assert_eq!(m.read(&mut [0; 4]).unwrap(), 0);
+        assert_eq!((m.remaining_sources(), m.total_sources()), (0, 3));
+
+        let mut g = MultiReader::growable();
+        assert_eq!(g.total_sources(), 0);
+        g.push(&b"x"[..]);
+        g.push(&b"y"[..]);
+        assert_eq!((g.remaining_sources(), g.total_sources()), (2, 2));
+    }
+
+    #[test]
+    fn test_source_offset() {
+        let sources = vec![&b"abc"[..], &b"defg"[..]];
+        let mut m = MultiReader::new(sources.into_iter());
+        assert_eq!(m.source_offset(), 0);
+        assert_eq!(m.read(&mut [0; 2]).unwrap(), 2);
+        assert_eq!((m.source_index(), m.source_offset()), (0, 2));
+        assert_eq!(m.read(&mut [0; 2]).unwrap(), 1);
+        assert_eq!((m.source_index(), m.source_offset()), (0, 3));
+
+        // Peeking does not move the position.
+        assert_eq!(m.fill_peek_buffer(3).unwrap(), b"def");
+        assert_eq!((m.source_index(), m.source_offset()), (0, 3));
+        assert_eq!(m.read(&mut [0; 2]).unwrap(), 2);
+        assert_eq!((m.source_index(), m.source_offset()), (1, 2));
+        assert_eq!(m.read(&mut [0; 2]).unwrap(), 1);
+        assert_eq!((m.source_index(), m.source_offset()), (1, 3));
+        assert_eq!(m.read(&mut [0; 2]).unwrap(), 1);
+        assert_eq!((m.source_index(), m.source_offset()), (1, 4));
+        assert_eq!(m.read(&mut [0; 2]).unwrap(), 0);
+        assert_eq!((m.source_index(), m.source_offset()), (2, 0));
+    }
+
+    #[test]
+    fn test_map_each() {
+        let sources = vec![&b"ab"[..], &b"cd"[..], &b"ef"[..]];
+        let entered = ::std::cell::RefCell::new(Vec::new());
+        let mut m = MultiReader::new(sources.into_iter())
+            .map_each(|i, r| {
+                entered.borrow_mut().push(i);
+                r.take(i as u64 + 1)
+            });
+        assert_eq!(*entered.borrow(), vec![0]);
+
+        let mut buf = [0; 10];
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!(&buf[..1], b"a");
+        assert_eq!(m.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"cd");
+        assert_eq!(*entered.borrow(), vec![0, 1]);
+        assert_eq!(m.read(&mut buf).unwrap(), 2);
+        assert_eq!(m.source_index(), 2);
+        assert_eq!(m.read(&mut buf).unwrap(), 0);
+        assert_eq!(*entered.borrow(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_filter_bytes() {
+        let sources = vec![&b"a\0b"[..], &b"\0\0"[..], &b""[..], &b"c\0"[..]];
+        let mut m = MultiReader::new(sources.into_iter()).filter_bytes(|b| b != 0);
+        let mut buf = [0; 10];
+        assert_eq!(m.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ab");
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!(&buf[..1], b"c");
+        assert_eq!(m.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_fallible_sources() {
+        let sources: Vec<io::Result<&[u8]>> = vec![Ok(b"a"), Err(io::Error::other("gone")), Ok(b"b")];
+        let mut m = MultiReader::new(sources.into_iter().map(Fallible::from));
+        let mut buf = [0; 4];
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        for _ in 0..2 {
+            assert_eq!(m.read(&mut buf).unwrap_err().to_string(), "gone");
+        }
+
+        let sources = vec![Err(io::Error::from(io::ErrorKind::Interrupted)), Ok(&b"b"[..])];
+        let mut m = MultiReader::new(sources.into_iter().map(Fallible::from));
+        let mut out = Vec::new();
+        assert_eq!(m.read_to_end(&mut out).unwrap_err().kind(), io::ErrorKind::Other);
+        assert_eq!(out, b"");
+    }
+
+    #[test]
+    fn test_empty_buffer_does_not_advance() {
+        let mut m = MultiReader::new(vec![&b"a"[..], &b"b"[..]].into_iter());
+        assert_eq!(m.read(&mut []).unwrap(), 0);
+        assert_eq!(m.source_index(), 0);
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"ab");
+    }
+
+    #[test]
+    fn test_eof_is_sticky() {
+        struct Flaky(u32);
+
+        impl Read for Flaky {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0 += 1;
+                if self.0.is_multiple_of(2) {
+                    buf[0] = b'!';
+                    return Ok(1);
                 }
+                Ok(0)
             }
         }
 
-        impl<R: Read> Read for MaybeErrReader<R> {
+        let mut pulled = 0u32;
+        let sources = ::std::iter::from_fn(|| {
+            pulled += 1;
+            if pulled.is_multiple_of(2) { Some(Flaky(0)) } else { None }
+        });
+        {
+            let mut m = MultiReader::new(sources);
+            for _ in 0..3 {
+                assert_eq!(m.read(&mut [0; 4]).unwrap(), 0);
+                assert!(m.is_finished());
+            }
+        }
+        assert_eq!(pulled, 1);
+
+        let sources = vec![Flaky(0)].into_iter().chain(::std::iter::once(Flaky(1)));
+        let mut m = MultiReader::new(sources);
+        assert_eq!(m.read(&mut [0; 4]).unwrap(), 1);
+        assert!(!m.is_finished());
+        assert_eq!(m.read(&mut [0; 4]).unwrap(), 0);
+        assert_eq!(m.read(&mut [0; 4]).unwrap(), 0);
+        assert!(m.is_finished());
+    }
+
+    #[test]
+    fn test_growable() {
+        let mut m = MultiReader::growable();
+        m.push(&b"ab"[..]);
+        m.push(&b"c"[..]);
+        assert_eq!(m.pending(), 1);
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abc");
+        assert_eq!(m.source_index(), 2);
+
+        m.push(&b"d"[..]);
+        assert_eq!(m.read(&mut [0; 4]).unwrap(), 0);
+        assert!(m.rearm());
+        assert_eq!(m.source_index(), 2);
+        out.clear();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"d");
+        assert_eq!(m.source_index(), 3);
+        assert!(!m.rearm());
+    }
+
+    #[test]
+    fn test_empty_read_policy() {
+        // Returns data only every third read, like a pseudo-file.
+        struct Sporadic(u32, u8);
+
+        impl Read for Sporadic {
             fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-                self.read_no += 1;
-                if self.read_no == self.fail_at + 1 {
-                    Err(io::Error::new(io::ErrorKind::Other, "I'm broken"))
-                } else {
-                    self.reader.read(buf)
+                self.0 += 1;
+                if self.0 > 6 || !self.0.is_multiple_of(3) {
+                    return Ok(0);
                 }
+                buf[0] = self.1;
+                Ok(1)
             }
         }
 
+        let sources = vec![Sporadic(0, b'a'), Sporadic(0, b'b')];
+        let mut m = MultiReader::new(sources.into_iter())
+            .empty_read_policy(|_, empty| {
+                if empty < 4 { EmptyRead::Retry } else { EmptyRead::Advance }
+            });
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"aabb");
+
+        let sources = vec![Sporadic(0, b'a'), Sporadic(0, b'b')];
+        let mut m = MultiReader::new(sources.into_iter())
+            .empty_read_policy(|index, empty| {
+                if index == 0 && empty < 3 { EmptyRead::Return } else { EmptyRead::Advance }
+            });
+        let mut buf = [0; 4];
+        let mut reads = Vec::new();
+        while !m.is_finished() {
+            reads.push(m.read(&mut buf).unwrap());
+        }
+        // Source "b" never gets a retry, so its data is not seen.
+        assert_eq!(reads, vec![0, 0, 1, 0, 0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_peek() {
+        let sources = vec![&b"ab"[..], &b""[..], &b"cd"[..], &b"ef"[..]];
+        let mut m = MultiReader::new(sources.into_iter());
+        assert_eq!(m.fill_peek_buffer(3).unwrap(), b"abc");
+        assert_eq!(m.source_index(), 0);
+
+        let mut magic = [0; 5];
+        assert_eq!(m.peek(&mut magic).unwrap(), 5);
+        assert_eq!(&magic, b"abcde");
+
+        let mut buf = [0; 10];
+        assert_eq!(m.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ab");
+        assert_eq!(m.source_index(), 0);
+        assert_eq!(m.read(&mut buf[..1]).unwrap(), 1);
+        assert_eq!(&buf[..1], b"c");
+        assert_eq!(m.source_index(), 2);
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!(&buf[..1], b"d");
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!(&buf[..1], b"e");
+        assert_eq!(m.source_index(), 3);
+
+        assert_eq!(m.peek(&mut buf).unwrap(), 1);
+        assert!(!m.is_finished());
+        assert_eq!(m.read(&mut buf).unwrap(), 1);
+        assert_eq!(&buf[..1], b"f");
+        assert!(m.is_finished());
+        assert_eq!(m.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_first_readers_is_empty() {}
+
+    #[test]
+    fn test_middle_readers_is_empty() {}
+
+    #[test]
+    fn test_last_readers_is_empty() {}
+
+    #[test]
+    fn test_only_empty_readers() {}
+
+    #[test]
+    fn test_err_during_read() {
         let s0 = MaybeErrReader::good(io::repeat(0).take(10));
         let s1 = MaybeErrReader::broken(io::repeat(0).take(2048), 1);
         let s2 = MaybeErrReader::good(io::repeat(0).take(10));
@@ -0,0 +1,293 @@
+//! Line iteration that keeps track of where each line came from.
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use multi_reader::lines::labeled_lines;
+//!
+//! fn main() {
+//!     let files = vec![("a.log", &b"one\ntwo\n"[..]), ("b.log", &b"three"[..])];
+//!     for line in labeled_lines(files) {
+//!         let (file, n, text) = line.unwrap();
+//!         println!("{}:{}: {}", file, n, text);
+//!     }
+//! }
+//! ```
+//!
+//! A source that does not end with a newline still ends its last line, so
+//! lines never span two sources.
+//!
+//! Like awk's `NR` and `FNR`, the iterator also tracks the number of lines
+//! seen overall and within the current source:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use multi_reader::lines::labeled_lines;
+//!
+//! fn main() {
+//!     let mut lines = labeled_lines(vec![("a", &b"x\ny\n"[..]), ("b", &b"z\n"[..])]);
+//!     while let Some(line) = lines.next() {
+//!         line.unwrap();
+//!         println!("NR={} FNR={}", lines.nr(), lines.fnr());
+//!     }
+//!     assert_eq!((lines.nr(), lines.fnr()), (3, 1));
+//! }
+//! ```
+//!
+//! `Prefixed` instead streams the chain with every line prefixed by a string
+//! derived from its source, without collecting lines:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//! use multi_reader::lines::Prefixed;
+//!
+//! fn main() {
+//!     let names = ["web", "db"];
+//!     let logs = vec![&b"up\nok\n"[..], &b"ready\n"[..]];
+//!     let mut reader = Prefixed::new(MultiReader::new(logs.into_iter()),
+//!                                    |i| format!("{}: ", names[i]));
+//!     let mut s = String::new();
+//!     reader.read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "web: up\nweb: ok\ndb: ready\n");
+//! }
+//! ```
+
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+
+use MultiReader;
+
+/// Iterator returned by `labeled_lines`.
+pub struct LabeledLines<L, R, I> {
+    sources: I,
+    current: Option<(L, BufReader<R>)>,
+    line_no: usize,
+    total: usize,
+}
+
+impl<L, R, I> LabeledLines<L, R, I> {
+    /// Number of lines yielded so far, awk's `NR`.
+    pub fn nr(&self) -> usize {
+        self.total
+    }
+
+    /// Number of lines yielded so far from the current source, awk's `FNR`.
+    pub fn fnr(&self) -> usize {
+        self.line_no
+    }
+}
+
+impl<L, R, I> Iterator for LabeledLines<L, R, I>
+    where L: Clone,
+          R: Read,
+          I: Iterator<Item = (L, R)>
+{
+    type Item = io::Result<(L, usize, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.current.is_none() {
+                let (label, reader) = self.sources.next()?;
+                self.current = Some((label, BufReader::new(reader)));
+                self.line_no = 0;
+            }
+            let mut line = Vec::new();
+            let (label, reader) = self.current.as_mut().unwrap();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => {}
+                Ok(_) => {
+                    if line.ends_with(b"\n") {
+                        line.pop();
+                        if line.ends_with(b"\r") {
+                            line.pop();
+                        }
+                    }
+                    // A line that is not UTF-8 still counts, so that the
+                    // lines after it keep their numbers.
+                    self.line_no += 1;
+                    self.total += 1;
+                    return Some(match String::from_utf8(line) {
+                        Ok(line) => Ok((label.clone(), self.line_no, line)),
+                        Err(_) => {
+                            let msg = format!("line {} is not valid UTF-8", self.line_no);
+                            Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+                        }
+                    });
+                }
+                Err(e) => return Some(Err(e)),
+            }
+            self.current = None;
+        }
+    }
+}
+
+/// Iterates over the lines of `(label, reader)` sources, yielding every line
+/// with the label of its source and its 1-based number within that source.
+pub fn labeled_lines<L, R, T>(sources: T) -> LabeledLines<L, R, T::IntoIter>
+    where L: Clone,
+          R: Read,
+          T: IntoIterator<Item = (L, R)>
+{
+    LabeledLines {
+        sources: sources.into_iter(),
+        current: None,
+        line_no: 0,
+        total: 0,
+    }
+}
+
+/// Prefixes every line of a chain with `prefix(source_index)`.
+///
+/// A source that ends in the middle of a line gets a newline appended, so
+/// that every output line comes from a single source.
+pub struct Prefixed<R, I, F> {
+    inner: MultiReader<R, I>,
+    prefix: F,
+    cached: Option<(usize, Vec<u8>)>,
+    source: Option<usize>,
+    line_start: bool,
+    chunk: Box<[u8]>,
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R, I, F, P> Prefixed<R, I, F>
+    where R: Read,
+          I: Iterator<Item = R>,
+          F: FnMut(usize) -> P,
+          P: AsRef<[u8]>
+{
+    pub fn new(inner: MultiReader<R, I>, prefix: F) -> Prefixed<R, I, F> {
+        Prefixed {
+            inner,
+            prefix,
+            cached: None,
+            source: None,
+            line_start: true,
+            chunk: vec![0; 4096].into_boxed_slice(),
+            out: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> MultiReader<R, I> {
+        self.inner
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        self.out.clear();
+        self.pos = 0;
+        let n = self.inner.read(&mut self.chunk)?;
+        if n == 0 {
+            return Ok(());
+        }
+        let index = self.inner.source_index();
+        if self.source.is_some_and(|s| s != index) && !self.line_start {
+            self.out.push(b'\n');
+            self.line_start = true;
+        }
+        self.source = Some(index);
+        if self.cached.as_ref().is_none_or(|c| c.0 != index) {
+            let prefix = (self.prefix)(index).as_ref().to_vec();
+            self.cached = Some((index, prefix));
+        }
+        let prefix = &self.cached.as_ref().unwrap().1;
+        for &b in &self.chunk[..n] {
+            if self.line_start {
+                self.out.extend_from_slice(prefix);
+            }
+            self.out.push(b);
+            self.line_start = b == b'\n';
+        }
+        Ok(())
+    }
+}
+
+impl<R, I, F, P> Read for Prefixed<R, I, F>
+    where R: Read,
+          I: Iterator<Item = R>,
+          F: FnMut(usize) -> P,
+          P: AsRef<[u8]>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.out.len() {
+            self.fill()?;
+        }
+        let n = ::std::cmp::min(buf.len(), self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use super::{labeled_lines, Prefixed};
+    use MultiReader;
+
+    #[test]
+    fn test_labeled_lines() {
+        let sources = vec![("a", &b"1\r\n2\n"[..]), ("b", &b""[..]), ("c", &b"x\n\ny"[..])];
+        let lines: Vec<_> = labeled_lines(sources).map(|l| l.unwrap()).collect();
+        assert_eq!(lines,
+                   vec![("a", 1, "1".to_string()),
+                        ("a", 2, "2".to_string()),
+                        ("c", 1, "x".to_string()),
+                        ("c", 2, "".to_string()),
+                        ("c", 3, "y".to_string())]);
+    }
+
+    #[test]
+    fn test_nr_fnr() {
+        let sources = vec![("a", &b"1\n2\n"[..]), ("b", &b""[..]), ("c", &b"3\n4\n5"[..])];
+        let mut lines = labeled_lines(sources);
+        assert_eq!((lines.nr(), lines.fnr()), (0, 0));
+        let mut seen = Vec::new();
+        while let Some(line) = lines.next() {
+            let (label, fnr, _) = line.unwrap();
+            assert_eq!(fnr, lines.fnr());
+            seen.push((label, lines.nr(), lines.fnr()));
+        }
+        assert_eq!(seen, vec![("a", 1, 1), ("a", 2, 2), ("c", 3, 1), ("c", 4, 2), ("c", 5, 3)]);
+    }
+
+    #[test]
+    fn test_prefixed() {
+        let sources = vec![&b"a\nb"[..], &b""[..], &b"c\n\n"[..], &b"d"[..]];
+        let mut calls = 0;
+        let mut p = Prefixed::new(MultiReader::new(sources.into_iter()), |i| {
+            calls += 1;
+            format!("[{}] ", i)
+        });
+        let mut s = String::new();
+        p.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "[0] a\n[0] b\n[2] c\n[2] \n[3] d");
+        drop(p);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_prefixed_small_reads() {
+        let sources = vec![&b"xy\n"[..], &b"z\n"[..]];
+        let mut p = Prefixed::new(MultiReader::new(sources.into_iter()), |_| "> ");
+        let mut out = Vec::new();
+        let mut buf = [0; 1];
+        while p.read(&mut buf).unwrap() == 1 {
+            out.push(buf[0]);
+        }
+        assert_eq!(out, b"> xy\n> z\n");
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let sources = vec![(0, &b"\xff\nok\n"[..])];
+        let mut lines = labeled_lines(sources);
+        assert_eq!(lines.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(lines.next().unwrap().unwrap(), (0, 2, "ok".to_string()));
+        assert_eq!((lines.nr(), lines.fnr()), (2, 2));
+        assert!(lines.next().is_none());
+    }
+}
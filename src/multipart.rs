@@ -0,0 +1,288 @@
+//! `multipart/form-data` bodies built over readers.
+//!
+//! Every field becomes one part; the part headers and boundaries are
+//! generated lazily around the field readers, so nothing is buffered.
+//! Reading the body fails if a content type has a line break in it or the
+//! data of a field contains the boundary:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::multipart::{self, Field};
+//!
+//! fn main() {
+//!     let fields = vec![
+//!         Field::new("note", &b"hi"[..]),
+//!         Field::new("upload", &b"data"[..]).file_name("a.txt").content_type("text/plain"),
+//!     ];
+//!     let mut body = multipart::body("XyZ", fields);
+//!     let mut s = String::new();
+//!     body.read_to_string(&mut s).unwrap();
+//!     assert!(s.starts_with("--XyZ\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhi"));
+//!     assert!(s.ends_with("data\r\n--XyZ--\r\n"));
+//!     assert_eq!(multipart::content_type("XyZ"), "multipart/form-data; boundary=XyZ");
+//! }
+//! ```
+
+use std::io;
+use std::io::{Cursor, Read};
+
+use MultiReader;
+
+pub struct Field<R> {
+    name: String,
+    file_name: Option<String>,
+    content_type: Option<String>,
+    reader: R,
+}
+
+impl<R: Read> Field<R> {
+    pub fn new(name: &str, reader: R) -> Field<R> {
+        Field {
+            name: name.to_string(),
+            file_name: None,
+            content_type: None,
+            reader,
+        }
+    }
+
+    pub fn file_name(mut self, file_name: &str) -> Field<R> {
+        self.file_name = Some(file_name.to_string());
+        self
+    }
+
+    pub fn content_type(mut self, content_type: &str) -> Field<R> {
+        self.content_type = Some(content_type.to_string());
+        self
+    }
+
+    fn headers(&self) -> io::Result<String> {
+        let mut h = format!("Content-Disposition: form-data; name=\"{}\"", escape(&self.name));
+        if let Some(ref file_name) = self.file_name {
+            h.push_str(&format!("; filename=\"{}\"", escape(file_name)));
+        }
+        h.push_str("\r\n");
+        if let Some(ref content_type) = self.content_type {
+            if content_type.contains(['\r', '\n']) {
+                let msg = format!("content type of field \"{}\" has a line break", escape(&self.name));
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+            h.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        h.push_str("\r\n");
+        Ok(h)
+    }
+}
+
+/// A piece of a multipart body: generated framing, a field's data, or the
+/// reason the field cannot be part of the body.
+pub enum Part<R> {
+    Framing(Cursor<Vec<u8>>),
+    Data(FieldData<R>),
+    Invalid(io::ErrorKind, String),
+}
+
+impl<R: Read> Read for Part<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Part::Framing(ref mut c) => c.read(buf),
+            Part::Data(ref mut r) => r.read(buf),
+            Part::Invalid(kind, ref msg) => Err(io::Error::new(kind, msg.clone())),
+        }
+    }
+}
+
+/// The data of a field, checked for the boundary as it is read.
+pub struct FieldData<R> {
+    reader: R,
+    delimiter: Vec<u8>,
+    // Last bytes read, the start of a delimiter split between two reads.
+    tail: Vec<u8>,
+    found: bool,
+}
+
+impl<R> FieldData<R> {
+    fn new(reader: R, delimiter: Vec<u8>) -> FieldData<R> {
+        FieldData {
+            reader,
+            tail: Vec::with_capacity(2 * delimiter.len()),
+            delimiter,
+            found: false,
+        }
+    }
+
+    /// Whether the delimiter is in `data`, or starts in the tail of the
+    /// previous reads and ends in `data`.
+    fn contains_delimiter(&self, data: &[u8]) -> bool {
+        let d = &self.delimiter[..];
+        let straddles = (1..d.len()).any(|k| {
+            k <= self.tail.len() && d.len() - k <= data.len() && self.tail.ends_with(&d[..k]) &&
+            data.starts_with(&d[k..])
+        });
+        straddles || data.windows(d.len()).any(|w| w == d)
+    }
+}
+
+impl<R: Read> Read for FieldData<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.found {
+            let n = self.reader.read(buf)?;
+            self.found = self.contains_delimiter(&buf[..n]);
+            if !self.found {
+                let keep = self.delimiter.len() - 1;
+                let data = &buf[n.saturating_sub(keep)..n];
+                self.tail.extend_from_slice(data);
+                let excess = self.tail.len().saturating_sub(keep);
+                self.tail.drain(..excess);
+                return Ok(n);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, "field data contains the boundary"))
+    }
+}
+
+/// Iterator yielding the parts of a multipart body.
+pub struct Parts<R, I> {
+    boundary: String,
+    fields: I,
+    data: Option<FieldData<R>>,
+    first: bool,
+    done: bool,
+}
+
+impl<R: Read, I: Iterator<Item = Field<R>>> Iterator for Parts<R, I> {
+    type Item = Part<R>;
+
+    fn next(&mut self) -> Option<Part<R>> {
+        if let Some(data) = self.data.take() {
+            return Some(Part::Data(data));
+        }
+        if self.done {
+            return None;
+        }
+        let lead = if self.first { "" } else { "\r\n" };
+        self.first = false;
+        let framing = match self.fields.next() {
+            Some(field) => {
+                let headers = match field.headers() {
+                    Ok(headers) => headers,
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Part::Invalid(e.kind(), e.to_string()));
+                    }
+                };
+                let framing = format!("{}--{}\r\n{}", lead, self.boundary, headers);
+                let delimiter = format!("--{}", self.boundary).into_bytes();
+                self.data = Some(FieldData::new(field.reader, delimiter));
+                framing
+            }
+            None => {
+                self.done = true;
+                format!("{}--{}--\r\n", lead, self.boundary)
+            }
+        };
+        Some(Part::Framing(Cursor::new(framing.into_bytes())))
+    }
+}
+
+pub type Body<R, I> = MultiReader<Part<R>, Parts<R, I>>;
+
+/// Builds a `multipart/form-data` body delimited by `boundary`.
+///
+/// Panics if `boundary` is empty or has a line break in it.
+pub fn body<R, I>(boundary: &str, fields: I) -> Body<R, I::IntoIter>
+    where R: Read,
+          I: IntoIterator<Item = Field<R>>
+{
+    assert!(!boundary.is_empty() && !boundary.contains(['\r', '\n']),
+            "a boundary must be a non-empty single line");
+    MultiReader::new(Parts {
+        boundary: boundary.to_string(),
+        fields: fields.into_iter(),
+        data: None,
+        first: true,
+        done: false,
+    })
+}
+
+/// Value of the `Content-Type` header matching a body built with `boundary`.
+pub fn content_type(boundary: &str) -> String {
+    format!("multipart/form-data; boundary={}", boundary)
+}
+
+fn escape(value: &str) -> String {
+    value.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use super::{body, Field};
+    use testing::ShortReader;
+
+    fn render(fields: Vec<Field<&[u8]>>) -> String {
+        let mut s = String::new();
+        body("b", fields).read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_body() {
+        let fields = vec![
+            Field::new("a", &b"1"[..]),
+            Field::new("f", &b"xyz"[..]).file_name("x.bin").content_type("application/octet-stream"),
+        ];
+        assert_eq!(render(fields),
+                   "--b\r\n\
+                    Content-Disposition: form-data; name=\"a\"\r\n\r\n\
+                    1\r\n\
+                    --b\r\n\
+                    Content-Disposition: form-data; name=\"f\"; filename=\"x.bin\"\r\n\
+                    Content-Type: application/octet-stream\r\n\r\n\
+                    xyz\r\n\
+                    --b--\r\n");
+    }
+
+    #[test]
+    fn test_empty_form() {
+        assert_eq!(render(vec![]), "--b--\r\n");
+    }
+
+    #[test]
+    fn test_escaped_names() {
+        let s = render(vec![Field::new("a\"b\r\n", &b""[..])]);
+        assert!(s.contains("name=\"a%22b%0D%0A\""));
+    }
+
+    #[test]
+    fn test_content_type_line_break() {
+        let fields = vec![Field::new("a", &b"1"[..]).content_type("text/plain\r\nX-Evil: 1")];
+        let mut s = String::new();
+        let err = body("b", fields).read_to_string(&mut s).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(!s.contains("X-Evil"));
+    }
+
+    #[test]
+    fn test_boundary_in_data() {
+        // The delimiter split across reads of the field is found too.
+        for chunk in 1..9 {
+            let data = ShortReader::new(&b"x\r\n--bound\r\n"[..], chunk);
+            let mut out = Vec::new();
+            let err = body("bound", vec![Field::new("a", data)]).read_to_end(&mut out).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            // Only the delimiter opening the part got out.
+            assert_eq!(out.windows(7).filter(|w| *w == b"--bound").count(), 1);
+        }
+        let data = ShortReader::new(&b"--boun-d--boun"[..], 3);
+        let mut out = Vec::new();
+        body("bound", vec![Field::new("a", data)]).read_to_end(&mut out).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "non-empty single line")]
+    fn test_boundary_line_break() {
+        let _ = body("b\r\nX: 1", Vec::<Field<&[u8]>>::new());
+    }
+}
@@ -0,0 +1,252 @@
+//! Several streams interleaved over one.
+//!
+//! Where a chain reads its sources one after another, `Mux` reads them all
+//! in turn, a chunk at a time, tagging every chunk with the index of its
+//! stream. `Demux` splits the result back into one reader per stream:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::mux::{Demux, Mux};
+//!
+//! fn main() {
+//!     let mut pipe = Vec::new();
+//!     Mux::new(vec![&b"stdout"[..], &b"stderr"[..]]).read_to_end(&mut pipe).unwrap();
+//!
+//!     let demux = Demux::new(&pipe[..]);
+//!     let (mut out, mut err) = (String::new(), String::new());
+//!     demux.stream(1).read_to_string(&mut err).unwrap();
+//!     demux.stream(0).read_to_string(&mut out).unwrap();
+//!     assert_eq!((&out[..], &err[..]), ("stdout", "stderr"));
+//! }
+//! ```
+//!
+//! A frame is the stream index and the chunk length, both little-endian
+//! `u32`s, followed by the chunk. A frame of length zero ends its stream.
+//! Chunks of other streams met while reading one are kept in memory until
+//! their streams are read.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::Read;
+use std::rc::Rc;
+
+use pool;
+
+pub struct Mux<R> {
+    streams: Vec<Option<R>>,
+    // Indexes of the streams not done with yet, the next one to read first.
+    turns: VecDeque<usize>,
+    chunk: Vec<u8>,
+    frame: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Mux<R> {
+    pub fn new<T: IntoIterator<Item = R>>(streams: T) -> Mux<R> {
+        let streams: Vec<_> = streams.into_iter().map(Some).collect();
+        assert!(streams.len() <= u32::MAX as usize, "too many streams");
+        Mux {
+            turns: (0..streams.len()).collect(),
+            streams,
+            chunk: vec![0; pool::DEFAULT_SIZE],
+            frame: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Reads a chunk of the stream whose turn it is, then moves it to the
+    /// back of the line, or drops it.
+    fn next_frame(&mut self) -> io::Result<bool> {
+        let index = match self.turns.front() {
+            Some(&index) => index,
+            None => return Ok(false),
+        };
+        let n = match self.streams[index] {
+            Some(ref mut stream) => stream.read(&mut self.chunk)?,
+            None => 0,
+        };
+        self.turns.pop_front();
+        if n > 0 {
+            self.turns.push_back(index);
+        } else {
+            self.streams[index] = None;
+        }
+        self.frame.clear();
+        self.frame.extend_from_slice(&(index as u32).to_le_bytes());
+        self.frame.extend_from_slice(&(n as u32).to_le_bytes());
+        self.frame.extend_from_slice(&self.chunk[..n]);
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for Mux<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.frame.len() && !self.next_frame()? {
+            return Ok(0);
+        }
+        let n = ::std::cmp::min(buf.len(), self.frame.len() - self.pos);
+        buf[..n].copy_from_slice(&self.frame[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// The receiving side of a `Mux`, handing out its streams.
+pub struct Demux<R> {
+    shared: Rc<RefCell<Shared<R>>>,
+}
+
+struct Shared<R> {
+    pipe: R,
+    streams: HashMap<u32, Pending>,
+    done: bool,
+}
+
+/// Chunks of a stream received but not read yet.
+#[derive(Default)]
+struct Pending {
+    chunks: VecDeque<Vec<u8>>,
+    // Bytes of the first chunk already read.
+    pos: usize,
+    ended: bool,
+}
+
+impl<R: Read> Demux<R> {
+    pub fn new(pipe: R) -> Demux<R> {
+        Demux {
+            shared: Rc::new(RefCell::new(Shared {
+                pipe,
+                streams: HashMap::new(),
+                done: false,
+            })),
+        }
+    }
+
+    /// A reader of the stream of `index`. Readers of the same stream share
+    /// its data.
+    pub fn stream(&self, index: u32) -> Stream<R> {
+        Stream {
+            shared: self.shared.clone(),
+            index,
+        }
+    }
+}
+
+impl<R: Read> Shared<R> {
+    /// Reads the next frame, or returns `false` at the end of the pipe.
+    fn receive(&mut self) -> io::Result<bool> {
+        let mut header = [0; 8];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.pipe.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => return Err(truncated()),
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut index = [0; 4];
+        let mut len = [0; 4];
+        index.copy_from_slice(&header[..4]);
+        len.copy_from_slice(&header[4..]);
+        let len = u32::from_le_bytes(len) as u64;
+        let mut chunk = Vec::new();
+        if (&mut self.pipe).take(len).read_to_end(&mut chunk)? < len as usize {
+            return Err(truncated());
+        }
+        let stream = self.streams.entry(u32::from_le_bytes(index)).or_default();
+        if len == 0 {
+            stream.ended = true;
+        } else {
+            stream.chunks.push_back(chunk);
+        }
+        Ok(true)
+    }
+}
+
+/// Reader behind `Demux::stream`.
+pub struct Stream<R> {
+    shared: Rc<RefCell<Shared<R>>>,
+    index: u32,
+}
+
+impl<R: Read> Read for Stream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut shared = self.shared.borrow_mut();
+        loop {
+            let ended = {
+                let stream = shared.streams.entry(self.index).or_default();
+                if let Some(chunk) = stream.chunks.front() {
+                    let n = ::std::cmp::min(buf.len(), chunk.len() - stream.pos);
+                    buf[..n].copy_from_slice(&chunk[stream.pos..stream.pos + n]);
+                    stream.pos += n;
+                    if stream.pos == chunk.len() {
+                        stream.chunks.pop_front();
+                        stream.pos = 0;
+                    }
+                    return Ok(n);
+                }
+                stream.ended
+            };
+            if ended {
+                return Ok(0);
+            }
+            if shared.done || !shared.receive()? {
+                shared.done = true;
+                let msg = format!("the pipe ended before stream {}", self.index);
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg));
+            }
+        }
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use super::{Demux, Mux};
+    use testing::ShortReader;
+
+    #[test]
+    fn test_interleaving() {
+        let streams = vec![ShortReader::new(&b"aaa"[..], 2), ShortReader::new(&b"b"[..], 2)];
+        let mut pipe = Vec::new();
+        Mux::new(streams).read_to_end(&mut pipe).unwrap();
+        let frames: &[&[u8]] = &[
+            b"\x00\x00\x00\x00\x02\x00\x00\x00aa",
+            b"\x01\x00\x00\x00\x01\x00\x00\x00b",
+            b"\x00\x00\x00\x00\x01\x00\x00\x00a",
+            b"\x01\x00\x00\x00\x00\x00\x00\x00",
+            b"\x00\x00\x00\x00\x00\x00\x00\x00",
+        ];
+        assert_eq!(pipe, frames.concat());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let streams: Vec<Vec<u8>> = vec![vec![1; 20_000], vec![], vec![3; 100]];
+        let mut pipe = Vec::new();
+        Mux::new(streams.iter().map(|s| &s[..])).read_to_end(&mut pipe).unwrap();
+        let demux = Demux::new(&pipe[..]);
+        let mut readers: Vec<_> = (0..3).map(|i| demux.stream(i)).collect();
+        for (i, expected) in streams.iter().enumerate().rev() {
+            let mut got = Vec::new();
+            readers[i].read_to_end(&mut got).unwrap();
+            assert_eq!(&got, expected);
+        }
+        // A stream the pipe never had.
+        let err = demux.stream(7).read(&mut [0; 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,111 @@
+//! Newline normalization.
+//!
+//! `Normalizer` turns `\r\n` and lone `\r` into `\n`. It keeps track of a
+//! trailing `\r` between reads, so a pair split across two sources of a chain
+//! still yields a single newline:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::{newline, MultiReader};
+//!
+//! fn main() {
+//!     let logs = vec![&b"a\r\nb\r"[..], &b"\nc\rd\n"[..]];
+//!     let mut reader = newline::Normalizer::new(MultiReader::new(logs.into_iter()));
+//!     let mut s = String::new();
+//!     reader.read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "a\nb\nc\nd\n");
+//! }
+//! ```
+
+use std::io;
+use std::io::Read;
+
+pub struct Normalizer<R> {
+    inner: R,
+    after_cr: bool,
+}
+
+impl<R: Read> Normalizer<R> {
+    pub fn new(inner: R) -> Normalizer<R> {
+        Normalizer {
+            inner,
+            after_cr: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Normalizer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut written = 0;
+            for i in 0..n {
+                let b = buf[i];
+                let after_cr = self.after_cr;
+                self.after_cr = b == b'\r';
+                match b {
+                    b'\r' => buf[written] = b'\n',
+                    b'\n' if after_cr => continue,
+                    _ => buf[written] = b,
+                }
+                written += 1;
+            }
+            // A read consisting of the `\n` half of a split pair only must
+            // not be mistaken for EOF.
+            if written > 0 {
+                return Ok(written);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use super::Normalizer;
+    use MultiReader;
+
+    fn normalize(sources: Vec<&[u8]>) -> Vec<u8> {
+        let mut n = Normalizer::new(MultiReader::new(sources.into_iter()));
+        let mut out = Vec::new();
+        n.read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(vec![b"a\r\nb\rc\nd"]), b"a\nb\nc\nd");
+        assert_eq!(normalize(vec![b"\r\r\n\n"]), b"\n\n\n");
+        assert_eq!(normalize(vec![]), b"");
+    }
+
+    #[test]
+    fn test_pair_split_across_sources() {
+        assert_eq!(normalize(vec![b"a\r", b"\nb"]), b"a\nb");
+        assert_eq!(normalize(vec![b"a\r", b"", b"\n", b"\n"]), b"a\n\n");
+        assert_eq!(normalize(vec![b"a\r", b"b"]), b"a\nb");
+    }
+
+    #[test]
+    fn test_lone_lf_read_is_not_eof() {
+        let mut n = Normalizer::new(MultiReader::new(vec![&b"\r"[..], &b"\n"[..], &b"x"[..]]
+            .into_iter()));
+        let mut buf = [0; 4];
+        assert_eq!(n.read(&mut buf).unwrap(), 1);
+        assert_eq!(n.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'x');
+        assert_eq!(n.read(&mut buf).unwrap(), 0);
+    }
+}
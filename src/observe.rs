@@ -0,0 +1,311 @@
+//! Watching the bytes of a chain go by.
+//!
+//! `MultiReader::tee` copies everything read into a writer:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//!
+//! fn main() {
+//!     let mut audit = Vec::new();
+//!     let mut s = String::new();
+//!     MultiReader::new(vec![&b"ab"[..], &b"cd"[..]].into_iter())
+//!         .tee(&mut audit)
+//!         .read_to_string(&mut s)
+//!         .unwrap();
+//!     assert_eq!(audit, b"abcd");
+//! }
+//! ```
+//!
+//! `MultiReader::inspect` merely shows every chunk read, along with the index
+//! of the source it came from:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//!
+//! fn main() {
+//!     let mut sizes = vec![0; 2];
+//!     let mut reader = MultiReader::new(vec![&b"ab"[..], &b"cde"[..]].into_iter())
+//!         .inspect(|i, bytes| sizes[i] += bytes.len());
+//!     reader.read_to_end(&mut Vec::new()).unwrap();
+//!     assert_eq!(sizes, vec![2, 3]);
+//! }
+//! ```
+//!
+//! `MultiReader::heartbeat` reports progress at most once per interval, so
+//! that a supervisor can tell a slow transfer from a hang:
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use std::time::Duration;
+//! use multi_reader::files;
+//!
+//! fn main() {
+//!     let mut reader = files::from_paths(vec!["a.log", "b.log"])
+//!         .into_reader()
+//!         .heartbeat(Duration::from_secs(10), |i, n| eprintln!("source {}, {} bytes", i, n));
+//!     reader.read_to_end(&mut Vec::new()).unwrap();
+//! }
+//! ```
+
+use std::io;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use MultiReader;
+
+/// Reader behind `MultiReader::tee`.
+///
+/// Bytes are written to the sink before `read` returns them. If writing
+/// fails, `read` returns the sink's error and keeps the bytes; the next
+/// `read` retries writing the rest of them before returning them, so the
+/// caller and the sink never miss a byte.
+pub struct Tee<R, W> {
+    inner: R,
+    sink: W,
+    pending: Vec<u8>,
+    written: usize,
+}
+
+impl<R: Read, W: Write> Tee<R, W> {
+    pub fn new(inner: R, sink: W) -> Tee<R, W> {
+        Tee {
+            inner,
+            sink,
+            pending: Vec::new(),
+            written: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn sink(&self) -> &W {
+        &self.sink
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.inner, self.sink)
+    }
+
+    fn flush_pending(&mut self) -> io::Result<()> {
+        while self.written < self.pending.len() {
+            match self.sink.write(&self.pending[self.written..]) {
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero,
+                                              "tee sink accepted no bytes"))
+                }
+                Ok(n) => self.written += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let n = self.inner.read(buf)?;
+            self.pending.extend_from_slice(&buf[..n]);
+            self.written = 0;
+        }
+        self.flush_pending()?;
+        // Hand out the kept bytes, which may be more than `buf` holds after
+        // a failed write.
+        let n = ::std::cmp::min(buf.len(), self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        self.written -= n;
+        Ok(n)
+    }
+}
+
+/// Reader behind `MultiReader::inspect`.
+pub struct Inspect<R, I, F> {
+    inner: MultiReader<R, I>,
+    f: F,
+}
+
+impl<R, I, F> Inspect<R, I, F>
+    where R: Read,
+          I: Iterator<Item = R>,
+          F: FnMut(usize, &[u8])
+{
+    pub fn new(inner: MultiReader<R, I>, f: F) -> Inspect<R, I, F> {
+        Inspect { inner, f }
+    }
+
+    pub fn get_ref(&self) -> &MultiReader<R, I> {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> MultiReader<R, I> {
+        self.inner
+    }
+}
+
+impl<R, I, F> Read for Inspect<R, I, F>
+    where R: Read,
+          I: Iterator<Item = R>,
+          F: FnMut(usize, &[u8])
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            (self.f)(self.inner.source_index(), &buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// Reader behind `MultiReader::heartbeat`.
+///
+/// The callback runs on the thread reading, once a read returns: a source
+/// blocking in `read` shows as heartbeats stopping.
+pub struct Heartbeat<R, I, F> {
+    inner: MultiReader<R, I>,
+    interval: Duration,
+    last: Instant,
+    read: u64,
+    f: F,
+}
+
+impl<R, I, F> Heartbeat<R, I, F>
+    where R: Read,
+          I: Iterator<Item = R>,
+          F: FnMut(usize, u64)
+{
+    pub fn new(inner: MultiReader<R, I>, interval: Duration, f: F) -> Heartbeat<R, I, F> {
+        Heartbeat {
+            inner,
+            interval,
+            last: Instant::now(),
+            read: 0,
+            f,
+        }
+    }
+
+    pub fn get_ref(&self) -> &MultiReader<R, I> {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> MultiReader<R, I> {
+        self.inner
+    }
+}
+
+impl<R, I, F> Read for Heartbeat<R, I, F>
+    where R: Read,
+          I: Iterator<Item = R>,
+          F: FnMut(usize, u64)
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        let now = Instant::now();
+        if now.duration_since(self.last) >= self.interval {
+            self.last = now;
+            (self.f)(self.inner.source_index(), self.read);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::{Read, Write};
+    use std::time::{Duration, Instant};
+    use MultiReader;
+
+    struct FailOnce {
+        data: Vec<u8>,
+        fail: bool,
+    }
+
+    impl Write for FailOnce {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.fail {
+                self.fail = false;
+                return Err(io::Error::other("disk full"));
+            }
+            // Short writes, to exercise resuming.
+            self.data.push(buf[0]);
+            Ok(1)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tee() {
+        let mut sink = Vec::new();
+        let mut out = Vec::new();
+        MultiReader::new(vec![&b"abc"[..], &b""[..], &b"d"[..]].into_iter())
+            .tee(&mut sink)
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, b"abcd");
+        assert_eq!(sink, b"abcd");
+    }
+
+    #[test]
+    fn test_inspect() {
+        let mut seen = Vec::new();
+        {
+            let mut m = MultiReader::new(vec![&b"abc"[..], &b""[..], &b"de"[..]].into_iter())
+                .inspect(|i, bytes| seen.push((i, bytes.to_vec())));
+            let mut out = Vec::new();
+            m.read_to_end(&mut out).unwrap();
+            assert_eq!(out, b"abcde");
+        }
+        assert_eq!(seen, vec![(0, b"abc".to_vec()), (2, b"de".to_vec())]);
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let mut beats = Vec::new();
+        {
+            let sources = vec![&b"abc"[..], &b"de"[..]];
+            let hour = Duration::from_secs(3600);
+            let mut m = MultiReader::new(sources.into_iter())
+                .heartbeat(hour, |i, n| beats.push((i, n)));
+            let mut buf = [0; 2];
+            m.read_exact(&mut buf).unwrap();
+            m.last = Instant::now() - hour;
+            m.read_exact(&mut buf).unwrap();
+            m.read_exact(&mut buf[..1]).unwrap();
+            m.last = Instant::now() - hour;
+            assert_eq!(m.read(&mut buf).unwrap(), 0);
+        }
+        assert_eq!(beats, vec![(0, 3), (2, 5)]);
+    }
+
+    #[test]
+    fn test_failing_sink() {
+        let sink = FailOnce {
+            data: Vec::new(),
+            fail: true,
+        };
+        let mut tee = MultiReader::new(vec![&b"abc"[..], &b"d"[..]].into_iter()).tee(sink);
+        let mut buf = [0; 8];
+        let err = tee.read(&mut buf).unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+        // The bytes read before the failure are still returned.
+        assert_eq!(tee.read(&mut buf[..2]).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ab");
+        assert_eq!(tee.read(&mut buf).unwrap(), 1);
+        assert_eq!(tee.read(&mut buf).unwrap(), 1);
+        assert_eq!(tee.read(&mut buf).unwrap(), 0);
+        assert_eq!(tee.sink().data, b"abcd");
+    }
+}
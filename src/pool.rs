@@ -0,0 +1,113 @@
+//! Scratch buffers for the helpers of a chain, reused instead of being
+//! allocated for every call.
+//!
+//! Every chain has a pool of its own unless given one with
+//! `MultiReader::buffer_pool`, which lets many chains share their buffers:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::sync::Arc;
+//! use multi_reader::MultiReader;
+//! use multi_reader::pool::BufferPool;
+//!
+//! fn main() {
+//!     let pool = Arc::new(BufferPool::new(64 * 1024, 16));
+//!     let chains: Vec<_> = (0..100)
+//!         .map(|_| MultiReader::new(vec![&b"log"[..]].into_iter()).buffer_pool(pool.clone()))
+//!         .collect();
+//! }
+//! ```
+
+use std::sync::Mutex;
+
+/// Default size of a scratch buffer.
+pub const DEFAULT_SIZE: usize = 8 * 1024;
+
+// A helper needs at most two buffers at a time, so a chain's own pool
+// keeping more around would only hold on to memory.
+pub(crate) const IN_USE: usize = 2;
+
+/// Buffers of one size, taken by chains for as long as a call needs them.
+pub struct BufferPool {
+    size: usize,
+    max_free: usize,
+    free: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    /// A pool of buffers of `size` bytes keeping up to `max_free` of them
+    /// for reuse when they are not in use.
+    pub fn new(size: usize, max_free: usize) -> BufferPool {
+        assert!(size > 0, "scratch buffers must not be empty");
+        BufferPool {
+            size,
+            max_free,
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.size
+    }
+
+    /// Most memory a chain takes from the pool at once.
+    pub(crate) fn max_bytes(&self) -> usize {
+        self.size * IN_USE
+    }
+
+    /// A buffer of `buffer_size` bytes with unspecified contents.
+    pub fn take(&self) -> Vec<u8> {
+        // Buffers hold no invariants a panic could break.
+        let buf = self.free.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        buf.unwrap_or_else(|| vec![0; self.size])
+    }
+
+    /// Returns a buffer taken from the pool.
+    pub fn put(&self, buf: Vec<u8>) {
+        if buf.len() == self.size {
+            let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+            if free.len() < self.max_free {
+                free.push(buf);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::sync::Arc;
+    use super::BufferPool;
+    use MultiReader;
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let pool = BufferPool::new(16, 2);
+        let buf = pool.take();
+        assert_eq!(buf.len(), 16);
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+        let again = pool.take();
+        assert_eq!(again.as_ptr(), ptr);
+        pool.put(again);
+
+        // Buffers of another size are not taken back.
+        pool.put(vec![0; 4]);
+        assert_eq!(pool.take().len(), 16);
+    }
+
+    #[test]
+    fn test_shared_pool() {
+        let pool = Arc::new(BufferPool::new(4, 8));
+        let mut a = MultiReader::new(vec![&b"abcdef"[..]].into_iter()).buffer_pool(pool.clone());
+        let mut b = MultiReader::new(vec![&b"ghijkl"[..]].into_iter()).buffer_pool(pool.clone());
+        assert_eq!(a.fill_peek_buffer(6).unwrap(), b"abcdef");
+        assert_eq!(pool.free.lock().unwrap().len(), 1);
+        // The second chain reuses the buffer of the first one.
+        let mut line = Vec::new();
+        b.read_until(b'\n', &mut line).unwrap();
+        assert_eq!(line, b"ghijkl");
+        assert_eq!(pool.free.lock().unwrap().len(), 1);
+        a.read_to_end(&mut Vec::new()).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+//! Positional reads from a concatenation of files.
+//!
+//! `FileChain::read_at` takes `&self` and reads with `pread` (or its Windows
+//! counterpart), so several threads can read different ranges of the
+//! concatenation at once, without a lock and without seeking:
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use std::fs::File;
+//! use std::thread;
+//! use multi_reader::positional::FileChain;
+//!
+//! fn main() {
+//!     let files = vec![File::open("seg.0").unwrap(), File::open("seg.1").unwrap()];
+//!     let chain = FileChain::new(files).unwrap();
+//!     thread::scope(|s| {
+//!         for i in 0..4 {
+//!             let chain = &chain;
+//!             s.spawn(move || {
+//!                 let mut block = [0; 4096];
+//!                 chain.read_exact_at(&mut block, i * 4096).unwrap();
+//!             });
+//!         }
+//!     });
+//! }
+//! ```
+
+use std::fs::File;
+use std::io;
+
+/// Files read as one concatenation through positional reads only.
+pub struct FileChain {
+    files: Vec<File>,
+    // Offset of every file in the concatenation, followed by its length.
+    starts: Vec<u64>,
+}
+
+impl FileChain {
+    /// Takes the files with their current lengths.
+    pub fn new(files: Vec<File>) -> io::Result<FileChain> {
+        let mut starts = vec![0];
+        for f in &files {
+            let end = starts[starts.len() - 1] + f.metadata()?.len();
+            starts.push(end);
+        }
+        Ok(FileChain { files, starts })
+    }
+
+    /// Number of bytes in the concatenation.
+    pub fn len(&self) -> u64 {
+        self.starts[self.starts.len() - 1]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn into_inner(self) -> Vec<File> {
+        self.files
+    }
+
+    /// Reads from `offset` of the concatenation, like `FileExt::read_at`.
+    /// A read never spans two files, so it may return fewer bytes than
+    /// requested; it returns 0 only at or past the end.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        if buf.is_empty() || offset >= self.len() {
+            return Ok(0);
+        }
+        let index = self.starts.partition_point(|&start| start <= offset) - 1;
+        let end = self.starts[index + 1];
+        let want = ::std::cmp::min(buf.len() as u64, end - offset) as usize;
+        let n = read_file_at(&self.files[index], &mut buf[..want], offset - self.starts[index])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      format!("file {} ended {} bytes early", index, end - offset)));
+        }
+        Ok(n)
+    }
+
+    /// Fills `buf` from `offset` of the concatenation, like
+    /// `FileExt::read_exact_at`.
+    pub fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "failed to fill whole buffer"))
+                }
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn read_file_at(f: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    f.read_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_file_at(f: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    f.seek_read(buf, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::fs::File;
+    use std::io;
+    use std::path::PathBuf;
+    use std::thread;
+    use super::FileChain;
+    use testing::temp_dir;
+
+    fn files(name: &str, contents: &[&[u8]]) -> (PathBuf, Vec<File>) {
+        let names: Vec<String> = (0..contents.len()).map(|i| i.to_string()).collect();
+        let named: Vec<(&str, &[u8])> =
+            names.iter().map(|n| &n[..]).zip(contents.iter().cloned()).collect();
+        let dir = temp_dir(name, &named);
+        let files = names.iter().map(|n| File::open(dir.join(n)).unwrap()).collect();
+        (dir, files)
+    }
+
+    #[test]
+    fn test_read_at() {
+        let (dir, files) = files("read_at", &[b"abc", b"", b"defg"]);
+        let chain = FileChain::new(files).unwrap();
+        assert_eq!(chain.len(), 7);
+        let mut buf = [0; 4];
+        assert_eq!(chain.read_at(&mut buf, 1).unwrap(), 2);
+        assert_eq!(&buf[..2], b"bc");
+        assert_eq!(chain.read_at(&mut buf, 7).unwrap(), 0);
+        chain.read_exact_at(&mut buf, 2).unwrap();
+        assert_eq!(&buf, b"cdef");
+        let err = chain.read_exact_at(&mut buf, 5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        thread::scope(|s| {
+            let chain = &chain;
+            let readers: Vec<_> = (0..7)
+                .map(|i| {
+                    s.spawn(move || {
+                        let mut b = [0; 1];
+                        chain.read_exact_at(&mut b, i).unwrap();
+                        b[0]
+                    })
+                })
+                .collect();
+            let bytes: Vec<u8> = readers.into_iter().map(|r| r.join().unwrap()).collect();
+            assert_eq!(bytes, b"abcdefg");
+        });
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
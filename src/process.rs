@@ -0,0 +1,209 @@
+//! Chaining the output of child processes.
+//!
+//! Every command is spawned only when the chain reaches it, and its stdout is
+//! read to the end before the next one starts:
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use std::process::Command;
+//! use multi_reader::process;
+//!
+//! fn main() {
+//!     let segments = vec!["seg.0", "seg.1"].into_iter().map(|s| {
+//!         let mut c = Command::new("zcat");
+//!         c.arg(s);
+//!         c
+//!     });
+//!     let mut reader = process::stdout_of(segments);
+//!     let mut data = Vec::new();
+//!     reader.read_to_end(&mut data).unwrap();
+//! }
+//! ```
+//!
+//! A command that cannot be spawned or exits unsuccessfully makes `read`
+//! fail at the point where its output ends, and every `read` after that
+//! too, see `Fallible`.
+//!
+//! The other way round, `pump` feeds a chain to a child process as its stdin:
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use std::fs::File;
+//! use std::process::Command;
+//! use multi_reader::{process, MultiReader};
+//!
+//! fn main() {
+//!     let logs = vec!["a.log", "b.log"].into_iter().map(|f| File::open(f).unwrap());
+//!     let (stdin, pumping) = process::pump(MultiReader::new(logs)).unwrap();
+//!     let status = Command::new("gzip").stdin(stdin).status().unwrap();
+//!     assert!(status.success());
+//!     pumping.join().unwrap().unwrap();
+//! }
+//! ```
+
+use std::io;
+use std::io::{PipeReader, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread;
+
+use {Fallible, MultiReader};
+
+/// The stdout of a spawned child process.
+pub struct ChildOutput {
+    program: String,
+    child: Child,
+    stdout: ChildStdout,
+    finished: bool,
+    // Kept to fail every read once the child exited unsuccessfully.
+    failure: Option<String>,
+}
+
+impl ChildOutput {
+    /// Spawns `command` with its stdout piped.
+    pub fn spawn(command: &mut Command) -> io::Result<ChildOutput> {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let mut child = command.stdout(Stdio::piped()).spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok(ChildOutput {
+            program,
+            child,
+            stdout,
+            finished: false,
+            failure: None,
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+}
+
+impl Read for ChildOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.finished {
+            return match self.failure {
+                Some(ref msg) => Err(io::Error::other(msg.clone())),
+                None => Ok(0),
+            };
+        }
+        let n = self.stdout.read(buf)?;
+        if n > 0 || buf.is_empty() {
+            return Ok(n);
+        }
+        let status = self.child.wait()?;
+        self.finished = true;
+        if !status.success() {
+            let msg = format!("{} exited with {}", self.program, status);
+            self.failure = Some(msg.clone());
+            return Err(io::Error::other(msg));
+        }
+        Ok(0)
+    }
+}
+
+impl Drop for ChildOutput {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Iterator spawning commands one at a time.
+pub struct Spawn<I> {
+    commands: I,
+}
+
+impl<I: Iterator<Item = Command>> Iterator for Spawn<I> {
+    type Item = Fallible<ChildOutput>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut command = self.commands.next()?;
+        Some(Fallible::from(ChildOutput::spawn(&mut command)))
+    }
+}
+
+/// Chains the stdout of `commands`, spawning each one lazily.
+pub fn stdout_of<I>(commands: I) -> MultiReader<Fallible<ChildOutput>, Spawn<I::IntoIter>>
+    where I: IntoIterator<Item = Command>
+{
+    MultiReader::new(Spawn { commands: commands.into_iter() })
+}
+
+/// Copies `reader` into an OS pipe on a background thread, returning the
+/// read end of the pipe, which converts into `Stdio`, along with the thread.
+/// The thread returns the number of bytes copied, or the error that stopped
+/// it; `BrokenPipe` if the read end was closed before the end of `reader`.
+pub fn pump<R>(mut reader: R) -> io::Result<(PipeReader, thread::JoinHandle<io::Result<u64>>)>
+    where R: Read + Send + 'static
+{
+    let (read_end, mut write_end) = io::pipe()?;
+    let pumping = thread::spawn(move || io::copy(&mut reader, &mut write_end));
+    Ok((read_end, pumping))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use std::process::{Command, Stdio};
+    use super::{pump, stdout_of};
+    use MultiReader;
+
+    fn sh(script: &str) -> Command {
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(script);
+        c
+    }
+
+    #[test]
+    fn test_chained_stdout() {
+        let mut out = String::new();
+        stdout_of(vec![sh("echo one"), sh("true"), sh("printf two")])
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "one\ntwo");
+    }
+
+    #[test]
+    fn test_failing_command() {
+        let mut m = stdout_of(vec![sh("echo partial; exit 3"), sh("echo next")]);
+        let mut out = String::new();
+        let err = m.read_to_string(&mut out).unwrap_err();
+        assert_eq!(err.to_string(), "sh exited with exit status: 3");
+        assert_eq!(out, "partial\n");
+
+        // The output of the next command is not mistaken for the rest.
+        out.clear();
+        assert!(m.read_to_string(&mut out).is_err());
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn test_pump_to_stdin() {
+        let chain = MultiReader::new(vec![&b"one\n"[..], &b"two\n"[..]].into_iter());
+        let (stdin, pumping) = pump(chain).unwrap();
+        let output = sh("tr a-z A-Z").stdin(stdin).stderr(Stdio::null()).output().unwrap();
+        assert_eq!(output.stdout, b"ONE\nTWO\n");
+        assert_eq!(pumping.join().unwrap().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_pump_closed_early() {
+        let big = vec![b'x'; 1 << 20];
+        let (stdin, pumping) = pump(io::Cursor::new(big)).unwrap();
+        drop(stdin);
+        let err = pumping.join().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_spawn_failure() {
+        let mut m = stdout_of(vec![Command::new("/nonexistent/program")]);
+        for _ in 0..2 {
+            assert_eq!(m.read(&mut [0; 4]).unwrap_err().kind(), io::ErrorKind::NotFound);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Throughput and ETA of a reader.
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::files;
+//! use multi_reader::progress::Progress;
+//!
+//! fn main() {
+//!     let chain = files::from_paths(vec!["a.log", "b.log"]).into_seekable().unwrap();
+//!     let total = chain.len();
+//!     let mut reader = Progress::new(chain).total_len(total);
+//!     let mut buf = [0; 64 * 1024];
+//!     while reader.read(&mut buf).unwrap() > 0 {
+//!         println!("{:.0} B/s, ETA {:?}", reader.throughput(), reader.eta());
+//!     }
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+// Samples closer together than window / SAMPLES are merged, which bounds
+// memory use however often `read` is called.
+const SAMPLES: u32 = 32;
+
+/// Counts the bytes read through it and estimates the current throughput
+/// over a rolling window, 5 seconds by default.
+pub struct Progress<R> {
+    inner: R,
+    read: u64,
+    total: Option<u64>,
+    window: Duration,
+    // (time, bytes read by then), oldest first.
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl<R: Read> Progress<R> {
+    pub fn new(inner: R) -> Progress<R> {
+        let mut samples = VecDeque::new();
+        samples.push_back((Instant::now(), 0));
+        Progress {
+            inner,
+            read: 0,
+            total: None,
+            window: Duration::from_secs(5),
+            samples,
+        }
+    }
+
+    /// Sets the number of bytes expected in total, which `eta` needs.
+    pub fn total_len(mut self, total: u64) -> Progress<R> {
+        self.total = Some(total);
+        self
+    }
+
+    /// Sets the duration over which throughput is averaged.
+    pub fn window(mut self, window: Duration) -> Progress<R> {
+        assert!(window > Duration::from_millis(0), "the window must not be empty");
+        self.window = window;
+        self
+    }
+
+    /// Number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    /// Bytes per second over the last window.
+    pub fn throughput(&self) -> f64 {
+        self.throughput_at(Instant::now())
+    }
+
+    /// Estimated time until `total_len` bytes are read, if it is set and
+    /// data is flowing.
+    pub fn eta(&self) -> Option<Duration> {
+        self.eta_at(Instant::now())
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn record(&mut self, now: Instant) {
+        let merge = self.window / SAMPLES;
+        let last = self.samples[self.samples.len() - 1].0;
+        if self.samples.len() > 1 && now.duration_since(last) < merge {
+            let n = self.samples.len();
+            self.samples[n - 1].1 = self.read;
+        } else {
+            self.samples.push_back((now, self.read));
+        }
+        // Keep the newest sample older than the window as the baseline.
+        while self.samples.len() > 1 && now.duration_since(self.samples[1].0) >= self.window {
+            self.samples.pop_front();
+        }
+    }
+
+    fn throughput_at(&self, now: Instant) -> f64 {
+        let (since, base) = self.samples[0];
+        let elapsed = now.duration_since(since).as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+        (self.read - base) as f64 / elapsed
+    }
+
+    fn eta_at(&self, now: Instant) -> Option<Duration> {
+        let left = self.total?.saturating_sub(self.read);
+        if left == 0 {
+            return Some(Duration::from_secs(0));
+        }
+        let rate = self.throughput_at(now);
+        if rate <= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(left as f64 / rate))
+    }
+}
+
+impl<R: Read> Read for Progress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        self.record(Instant::now());
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+    use super::Progress;
+
+    #[test]
+    fn test_throughput_and_eta() {
+        let start = Instant::now();
+        let mut p = Progress::new(&[0u8; 10][..]).total_len(400).window(Duration::from_secs(2));
+        p.samples[0].0 = start;
+        for i in 1..=4 {
+            p.read += 50;
+            p.record(start + Duration::from_secs(i));
+        }
+        let now = start + Duration::from_secs(4);
+        // 100 bytes over the last two seconds.
+        assert_eq!(p.throughput_at(now), 50.0);
+        assert_eq!(p.eta_at(now), Some(Duration::from_secs(4)));
+        // Nothing read for a while.
+        assert_eq!(p.throughput_at(start + Duration::from_secs(6)), 25.0);
+        assert!(p.samples.len() <= 3);
+    }
+
+    #[test]
+    fn test_counts_bytes() {
+        let mut p = Progress::new(&b"abcdef"[..]);
+        assert_eq!(p.eta(), None);
+        p.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(p.bytes_read(), 6);
+        let p = p.total_len(6);
+        assert_eq!(p.eta(), Some(Duration::from_secs(0)));
+    }
+}
@@ -0,0 +1,275 @@
+//! Chains built from byte-range requests.
+//!
+//! A resource of known length is split into segments, each of which is
+//! requested through a `Transport` (an HTTP client issuing `Range` requests,
+//! typically) only when the chain reaches it:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io;
+//! use std::io::Read;
+//! use std::ops::Range;
+//! use multi_reader::range::{self, Transport};
+//!
+//! struct Memory(&'static [u8]);
+//!
+//! impl Transport for Memory {
+//!     type Reader = &'static [u8];
+//!
+//!     fn fetch(&mut self, range: Range<u64>) -> io::Result<&'static [u8]> {
+//!         Ok(&self.0[range.start as usize..range.end as usize])
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut reader = range::segments(Memory(b"0123456789"), 10, 4);
+//!     let mut s = String::new();
+//!     reader.read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "0123456789");
+//! }
+//! ```
+//!
+//! `prefetched` downloads segments on a background thread instead, keeping up
+//! to `depth` of them buffered ahead of the one being read.
+//!
+//! A segment that cannot be fetched makes every `read` from it on fail, see
+//! `Fallible`, rather than serve the rest of the resource without it.
+
+use std::io;
+use std::io::{Cursor, Read};
+use std::ops::Range;
+use std::sync::mpsc;
+use std::thread;
+use std::vec;
+
+use {Fallible, MultiReader};
+
+/// Fetches byte ranges of one resource.
+pub trait Transport {
+    type Reader: Read;
+
+    /// Starts fetching `range` (end-exclusive) of the resource.
+    fn fetch(&mut self, range: Range<u64>) -> io::Result<Self::Reader>;
+}
+
+/// Splits `0..len` into consecutive ranges of `segment_len` bytes, the last
+/// one possibly shorter.
+pub fn split(len: u64, segment_len: u64) -> Vec<Range<u64>> {
+    assert!(segment_len > 0, "segment length must be positive");
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < len {
+        let end = ::std::cmp::min(start + segment_len, len);
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+/// One segment of the resource. It fails with `UnexpectedEof` if the
+/// transport delivers less than the requested range.
+pub struct Segment<R> {
+    inner: io::Take<R>,
+    range: Range<u64>,
+}
+
+impl<R: Read> Segment<R> {
+    fn new(inner: R, range: Range<u64>) -> Segment<R> {
+        Segment {
+            inner: inner.take(range.end - range.start),
+            range,
+        }
+    }
+
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+}
+
+impl<R: Read> Read for Segment<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() && self.inner.limit() > 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      format!("range {}..{} ended {} bytes early",
+                                              self.range.start,
+                                              self.range.end,
+                                              self.inner.limit())));
+        }
+        Ok(n)
+    }
+}
+
+/// Iterator fetching one segment at a time.
+pub struct Segments<T> {
+    transport: T,
+    ranges: vec::IntoIter<Range<u64>>,
+}
+
+impl<T: Transport> Iterator for Segments<T> {
+    type Item = Fallible<Segment<T::Reader>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let range = self.ranges.next()?;
+        let fetched = self.transport.fetch(range.clone()).map(|r| Segment::new(r, range));
+        Some(Fallible::from(fetched))
+    }
+}
+
+/// Iterator receiving segments downloaded by a background thread.
+pub struct Prefetched {
+    segments: mpsc::IntoIter<io::Result<Segment<Cursor<Vec<u8>>>>>,
+}
+
+impl Iterator for Prefetched {
+    type Item = Fallible<Segment<Cursor<Vec<u8>>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.segments.next().map(Fallible::from)
+    }
+}
+
+/// Chains `len` bytes of a resource, fetching `segment_len` bytes at a time
+/// as the chain advances.
+pub fn segments<T: Transport>(transport: T,
+                              len: u64,
+                              segment_len: u64)
+                              -> MultiReader<Fallible<Segment<T::Reader>>, Segments<T>> {
+    MultiReader::new(Segments {
+        transport,
+        ranges: split(len, segment_len).into_iter(),
+    })
+}
+
+/// Like `segments`, but a background thread downloads segments into memory
+/// while earlier ones are being read, staying at most `depth` segments
+/// ahead, and at least one. The thread stops when the chain is dropped.
+pub fn prefetched<T>(mut transport: T,
+                     len: u64,
+                     segment_len: u64,
+                     depth: usize)
+                     -> MultiReader<Fallible<Segment<Cursor<Vec<u8>>>>, Prefetched>
+    where T: Transport + Send + 'static
+{
+    let ranges = split(len, segment_len);
+    // The thread holds one more segment while waiting for room.
+    let (tx, rx) = mpsc::sync_channel(depth.saturating_sub(1));
+    thread::spawn(move || {
+        for range in ranges {
+            let downloaded = transport.fetch(range.clone()).and_then(|r| {
+                let mut data = Vec::new();
+                Segment::new(r, range.clone()).read_to_end(&mut data)?;
+                Ok(Segment::new(Cursor::new(data), range))
+            });
+            let failed = downloaded.is_err();
+            if tx.send(downloaded).is_err() || failed {
+                break;
+            }
+        }
+    });
+    MultiReader::new(Prefetched { segments: rx.into_iter() })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use std::ops::Range;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use super::{prefetched, segments, split, Transport};
+
+    struct Memory {
+        data: Vec<u8>,
+        requests: Arc<Mutex<Vec<Range<u64>>>>,
+        short_at: Option<u64>,
+    }
+
+    impl Memory {
+        fn new(len: usize) -> Memory {
+            Memory {
+                data: (0..len).map(|i| i as u8).collect(),
+                requests: Arc::new(Mutex::new(Vec::new())),
+                short_at: None,
+            }
+        }
+    }
+
+    impl Transport for Memory {
+        type Reader = io::Cursor<Vec<u8>>;
+
+        fn fetch(&mut self, range: Range<u64>) -> io::Result<Self::Reader> {
+            self.requests.lock().unwrap().push(range.clone());
+            let mut end = range.end as usize;
+            if self.short_at == Some(range.start) {
+                end -= 1;
+            }
+            Ok(io::Cursor::new(self.data[range.start as usize..end].to_vec()))
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split(10, 4), vec![0..4, 4..8, 8..10]);
+        assert_eq!(split(8, 4), vec![0..4, 4..8]);
+        assert!(split(0, 4).is_empty());
+    }
+
+    #[test]
+    fn test_segments_are_fetched_lazily() {
+        let transport = Memory::new(10);
+        let requests = transport.requests.clone();
+        let mut m = segments(transport, 10, 4);
+        assert_eq!(*requests.lock().unwrap(), vec![0..4]);
+
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, (0..10).collect::<Vec<u8>>());
+        assert_eq!(*requests.lock().unwrap(), vec![0..4, 4..8, 8..10]);
+    }
+
+    #[test]
+    fn test_short_segment() {
+        let mut transport = Memory::new(10);
+        transport.short_at = Some(4);
+        let mut out = Vec::new();
+        let err = segments(transport, 10, 4).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), "range 4..8 ended 1 bytes early");
+    }
+
+    #[test]
+    fn test_prefetched() {
+        let mut out = Vec::new();
+        prefetched(Memory::new(1000), 1000, 64, 2).read_to_end(&mut out).unwrap();
+        assert_eq!(out, (0..1000).map(|i| i as u8).collect::<Vec<u8>>());
+
+        let mut transport = Memory::new(10);
+        transport.short_at = Some(8);
+        let mut m = prefetched(transport, 10, 4, 1);
+        let mut out = Vec::new();
+        let err = m.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(out.len(), 8);
+    }
+
+    #[test]
+    fn test_prefetch_depth() {
+        let transport = Memory::new(100);
+        let requests = transport.requests.clone();
+        let mut m = prefetched(transport, 100, 10, 2);
+        assert_eq!(m.read(&mut [0; 1]).unwrap(), 1);
+        // Waits for the thread to fill the channel: it then blocks with the
+        // one being read and two ahead fetched.
+        for _ in 0..1000 {
+            if requests.lock().unwrap().len() >= 3 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        // Gives a fetch too many time to show up; the count cannot go on.
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,305 @@
+//! Network sources that reconnect when the connection drops.
+//!
+//! `Connections` calls a factory for a fresh stream every time the previous
+//! one is reset, so a chain over it reads through connection drops as if
+//! they were source boundaries:
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use std::net::TcpStream;
+//! use std::time::Duration;
+//! use multi_reader::MultiReader;
+//! use multi_reader::reconnect::Connections;
+//!
+//! fn main() {
+//!     let connections = Connections::new(|| TcpStream::connect("10.0.0.7:9000"))
+//!         .max_reconnects(10)
+//!         .backoff(Duration::from_millis(100), Duration::from_secs(5));
+//!     let mut reader = MultiReader::new(connections);
+//!     let mut data = Vec::new();
+//!     reader.read_to_end(&mut data).unwrap();
+//! }
+//! ```
+//!
+//! A connection closed cleanly by the peer ends the chain, unless
+//! `reconnect_on_eof` is set. Every reconnect attempt waits for the backoff
+//! delay first, which doubles after each failed attempt up to its maximum.
+//! A failed attempt makes `read` return its error, and the next `read`
+//! tries again. Once `max_reconnects` is used up the chain ends, or, if the
+//! last attempt failed, every `read` returns its error.
+
+use std::io;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use not_interrupted;
+
+/// A stream that treats being reset by the peer as EOF, or a failed
+/// attempt to connect.
+pub struct Connection<S> {
+    inner: Result<S, Option<io::Error>>,
+    dropped: bool,
+    eof_is_drop: bool,
+    // Set once the peer closes the connection cleanly, to end the chain.
+    closed: Arc<AtomicBool>,
+}
+
+impl<S: Read> Connection<S> {
+    /// The stream, `None` if connecting failed.
+    pub fn get_ref(&self) -> Option<&S> {
+        self.inner.as_ref().ok()
+    }
+
+    pub fn is_dropped(&self) -> bool {
+        self.dropped
+    }
+}
+
+impl<S: Read> Read for Connection<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inner = match self.inner {
+            Ok(ref mut inner) => inner,
+            // Returned once, so that the next read makes another attempt.
+            Err(ref mut e) => return e.take().map_or(Ok(0), Err),
+        };
+        if self.dropped {
+            return Ok(0);
+        }
+        match inner.read(buf) {
+            Ok(0) if !buf.is_empty() => {
+                self.dropped = true;
+                if !self.eof_is_drop {
+                    self.closed.store(true, Ordering::SeqCst);
+                }
+                Ok(0)
+            }
+            Err(ref e) if is_drop(e) => {
+                self.dropped = true;
+                Ok(0)
+            }
+            result => result,
+        }
+    }
+}
+
+fn is_drop(e: &io::Error) -> bool {
+    matches!(e.kind(),
+             io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted |
+             io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof)
+}
+
+/// Iterator of connections produced by a factory.
+pub struct Connections<F> {
+    factory: F,
+    connected: bool,
+    reconnects: usize,
+    max_reconnects: Option<usize>,
+    initial_delay: Duration,
+    max_delay: Duration,
+    delay: Duration,
+    eof_is_drop: bool,
+    closed: Arc<AtomicBool>,
+    // Error of the last attempt, if it failed.
+    failure: Option<(io::ErrorKind, String)>,
+}
+
+impl<S: Read, F: FnMut() -> io::Result<S>> Connections<F> {
+    pub fn new(factory: F) -> Connections<F> {
+        Connections {
+            factory,
+            connected: false,
+            reconnects: 0,
+            max_reconnects: None,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            delay: Duration::from_millis(100),
+            eof_is_drop: false,
+            closed: Arc::new(AtomicBool::new(false)),
+            failure: None,
+        }
+    }
+
+    /// Limits the number of reconnect attempts, failed ones included.
+    /// Unlimited by default.
+    pub fn max_reconnects(mut self, max: usize) -> Connections<F> {
+        self.max_reconnects = Some(max);
+        self
+    }
+
+    /// Waits `initial` before a reconnect attempt, doubling the delay after
+    /// every failed one up to `max`. 100ms up to 10s by default.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Connections<F> {
+        self.initial_delay = initial;
+        self.max_delay = max;
+        self.delay = initial;
+        self
+    }
+
+    /// Reconnects when the peer closes the connection cleanly too, for
+    /// servers that hang up on idle clients.
+    pub fn reconnect_on_eof(mut self) -> Connections<F> {
+        self.eof_is_drop = true;
+        self
+    }
+
+    /// Number of reconnect attempts made so far.
+    pub fn reconnects(&self) -> usize {
+        self.reconnects
+    }
+
+    fn connect(&mut self) -> Connection<S> {
+        let inner = match (self.factory)() {
+            Ok(inner) => {
+                self.delay = self.initial_delay;
+                self.failure = None;
+                Ok(inner)
+            }
+            Err(e) => {
+                self.delay = ::std::cmp::min(self.delay * 2, self.max_delay);
+                let e = not_interrupted(e);
+                self.failure = Some((e.kind(), e.to_string()));
+                Err(Some(e))
+            }
+        };
+        self.connection(inner)
+    }
+
+    fn connection(&self, inner: Result<S, Option<io::Error>>) -> Connection<S> {
+        Connection {
+            inner,
+            dropped: false,
+            eof_is_drop: self.eof_is_drop,
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+impl<S: Read, F: FnMut() -> io::Result<S>> Iterator for Connections<F> {
+    type Item = Connection<S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.connected {
+            self.connected = true;
+            return Some(self.connect());
+        }
+        if self.closed.load(Ordering::SeqCst) {
+            return None;
+        }
+        if self.max_reconnects.is_some_and(|max| self.reconnects >= max) {
+            let (kind, ref msg) = *self.failure.as_ref()?;
+            return Some(self.connection(Err(Some(io::Error::new(kind, msg.clone())))));
+        }
+        self.reconnects += 1;
+        if self.delay > Duration::from_millis(0) {
+            thread::sleep(self.delay);
+        }
+        Some(self.connect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+    use super::Connections;
+    use MultiReader;
+
+    struct Reset<'a>(&'a [u8]);
+
+    impl<'a> Read for Reset<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+            }
+            self.0.read(buf)
+        }
+    }
+
+    fn no_backoff<F>(connections: Connections<F>) -> Connections<F>
+        where F: FnMut() -> io::Result<Reset<'static>>
+    {
+        connections.backoff(Duration::from_millis(0), Duration::from_millis(0))
+    }
+
+    #[test]
+    fn test_reset_is_a_boundary() {
+        let mut chunks = vec![&b"ab"[..], &b"cd"[..]].into_iter();
+        let connections = Connections::new(move || Ok(Reset(chunks.next().unwrap_or(b""))))
+            .max_reconnects(2);
+        let mut out = Vec::new();
+        MultiReader::new(no_backoff(connections)).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abcd");
+    }
+
+    #[test]
+    fn test_failed_reconnects() {
+        let mut attempt = 0;
+        let connections = Connections::new(move || {
+                attempt += 1;
+                match attempt {
+                    1 => Ok(Reset(b"ab")),
+                    3 => Ok(Reset(b"cd")),
+                    _ => Err(io::Error::new(io::ErrorKind::ConnectionRefused, "refused")),
+                }
+            })
+            .max_reconnects(3);
+        let mut m = MultiReader::new(no_backoff(connections));
+        let mut out = Vec::new();
+        let err = m.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(out, b"ab");
+        // Every failed attempt is reported, and the next read tries again.
+        let err = m.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(out, b"abcd");
+        // The last allowed attempt failed: the chain keeps failing.
+        for _ in 0..2 {
+            let err = m.read(&mut [0; 4]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        }
+    }
+
+    #[test]
+    fn test_tcp_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            for payload in &[&b"first "[..], &b"second"[..]] {
+                let (mut s, _) = listener.accept().unwrap();
+                s.write_all(payload).unwrap();
+            }
+        });
+
+        let connections = Connections::new(|| TcpStream::connect(addr))
+            .max_reconnects(1)
+            .reconnect_on_eof();
+        let mut out = String::new();
+        MultiReader::new(connections).read_to_string(&mut out).unwrap();
+        assert_eq!(out, "first second");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_clean_close_ends_the_chain() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            s.write_all(b"done").unwrap();
+        });
+
+        let mut out = String::new();
+        MultiReader::new(Connections::new(|| TcpStream::connect(addr)))
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "done");
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! Reading borrowed sources ahead on scoped threads.
+//!
+//! `readahead` reads several sources of a chain at once, each on a worker
+//! thread, while the chain is consumed in order. The threads are scoped, so
+//! the sources only need to be borrowed, and are all done with once
+//! `readahead` returns:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::{Cursor, Read};
+//! use multi_reader::scoped;
+//!
+//! fn main() {
+//!     let mut a = Cursor::new(b"abc".to_vec());
+//!     let mut b = Cursor::new(b"def".to_vec());
+//!     let sources: Vec<&mut (dyn Read + Send)> = vec![&mut a, &mut b];
+//!     let s = scoped::readahead(sources, 2, |mut chain| {
+//!         let mut s = String::new();
+//!         chain.read_to_string(&mut s).map(|_| s)
+//!     });
+//!     assert_eq!(s.unwrap(), "abcdef");
+//!     assert_eq!(a.position(), 3);
+//! }
+//! ```
+//!
+//! Each worker keeps at most a couple of chunks read ahead, so memory use
+//! is bounded by the number of threads rather than the size of the sources.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::vec;
+
+use pool;
+use MultiReader;
+
+// Chunks read ahead per source before its worker waits for the chain.
+const CHUNKS_AHEAD: usize = 2;
+
+type Job<'a> = (&'a mut (dyn Read + Send), mpsc::SyncSender<io::Result<Vec<u8>>>);
+
+/// Calls `f` with a chain over `sources`, up to `threads` of which are read
+/// at once on scoped threads, in order, and returns what `f` returns.
+/// Sources the chain did not get to when `f` returns are not read further.
+pub fn readahead<'a, S, T, F>(sources: S, threads: usize, f: F) -> T
+    where S: IntoIterator<Item = &'a mut (dyn Read + Send)>,
+          F: FnOnce(MultiReader<Incoming, vec::IntoIter<Incoming>>) -> T
+{
+    assert!(threads > 0, "at least one thread is needed");
+    let mut jobs = VecDeque::new();
+    let mut incoming = Vec::new();
+    for source in sources {
+        let (tx, rx) = mpsc::sync_channel(CHUNKS_AHEAD);
+        jobs.push_back((source, tx));
+        incoming.push(Incoming {
+            chunks: rx,
+            chunk: Vec::new(),
+            pos: 0,
+        });
+    }
+    let jobs = Mutex::new(jobs);
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| work(&jobs, &stop));
+        }
+        let result = f(MultiReader::new(incoming.into_iter()));
+        stop.store(true, Ordering::SeqCst);
+        result
+    })
+}
+
+/// Takes sources in order and reads each to the end, or until the chain
+/// is dropped.
+fn work(jobs: &Mutex<VecDeque<Job<'_>>>, stop: &AtomicBool) {
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+        let job = jobs.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+        let (source, tx) = match job {
+            Some(job) => job,
+            None => return,
+        };
+        loop {
+            let mut chunk = vec![0; pool::DEFAULT_SIZE];
+            let sent = match source.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    chunk.truncate(n);
+                    tx.send(Ok(chunk))
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // Passed on like any other read, after which the chain reads
+                // more of the source as it would without readahead.
+                Err(e) => tx.send(Err(e)),
+            };
+            if sent.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// A source as read by its worker thread.
+pub struct Incoming {
+    chunks: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for Incoming {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos == self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // The worker is done with the source.
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = ::std::cmp::min(buf.len(), self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::{Cursor, Read};
+    use std::sync::{Arc, Barrier};
+    use super::readahead;
+    use testing::MaybeErrReader;
+
+    /// Blocks its first read until all its siblings are being read too.
+    struct Rendezvous<R> {
+        barrier: Option<Arc<Barrier>>,
+        inner: R,
+    }
+
+    impl<R: Read> Read for Rendezvous<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let Some(barrier) = self.barrier.take() {
+                barrier.wait();
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_sources_are_read_at_once() {
+        let barrier = Arc::new(Barrier::new(3));
+        let mut sources: Vec<_> = (0..3u8)
+            .map(|i| {
+                Rendezvous {
+                    barrier: Some(barrier.clone()),
+                    inner: Cursor::new(vec![b'a' + i; 20_000]),
+                }
+            })
+            .collect();
+        let out = readahead(sources.iter_mut().map(|s| s as &mut (dyn Read + Send)), 3, |mut m| {
+            let mut out = Vec::new();
+            m.read_to_end(&mut out).unwrap();
+            out
+        });
+        let expected: Vec<u8> = (0..3u8).flat_map(|i| vec![b'a' + i; 20_000]).collect();
+        assert_eq!(out, expected);
+        assert!(sources.iter().all(|s| s.inner.position() == 20_000));
+    }
+
+    #[test]
+    fn test_errors_and_early_return() {
+        let mut broken = MaybeErrReader::broken(&b"ab"[..], 0);
+        let mut good = &b"cd"[..];
+        let mut unread = Cursor::new(vec![0; 1 << 20]);
+        let sources: Vec<&mut (dyn Read + Send)> = vec![&mut broken, &mut good, &mut unread];
+        readahead(sources, 1, |mut m| {
+            let mut out = Vec::new();
+            assert!(m.read_to_end(&mut out).is_err());
+            let mut buf = [0; 4];
+            m.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"abcd");
+        });
+        assert!(unread.position() < 1 << 20);
+    }
+}
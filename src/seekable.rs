@@ -0,0 +1,340 @@
+//! Chains of seekable sources of known lengths, opened on demand.
+//!
+//! Instead of an iterator, the chain gets the length of every source and a
+//! function opening source `i`. Knowing where each source starts in the
+//! concatenation, it can hand out independent views over any byte range of
+//! it, each opening its own handles on the sources it covers:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::{Cursor, Read};
+//! use multi_reader::seekable::SeekableMultiReader;
+//!
+//! fn main() {
+//!     let segments = [&b"obj1obj"[..], &b"2obj3"[..]];
+//!     let pack = SeekableMultiReader::new(segments.iter().map(|s| s.len() as u64),
+//!                                         |i| Ok(Cursor::new(segments[i])));
+//!     let mut obj2 = String::new();
+//!     pack.sub_reader(4..8).read_to_string(&mut obj2).unwrap();
+//!     assert_eq!(obj2, "obj2");
+//! }
+//! ```
+//!
+//! The chain is also `Seek`: seeking within the source being read seeks it,
+//! and seeking anywhere else, including back into a source already
+//! finished, opens the target source anew, unless `max_open` lets the chain
+//! keep it open from an earlier read.
+
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Reads the sources opened by `open` one after another.
+///
+/// A source that turns out shorter than its declared length makes `read`
+/// fail with `UnexpectedEof`; bytes past its declared length are ignored.
+pub struct SeekableMultiReader<R, F> {
+    open: F,
+    // Offset of every source in the concatenation, followed by its length.
+    starts: Arc<Vec<u64>>,
+    window: Range<u64>,
+    pos: u64,
+    // Open sources, the most recently read last.
+    cache: Vec<(usize, R)>,
+    max_open: usize,
+    // Whether the last source in the cache is positioned at `pos`.
+    positioned: bool,
+}
+
+impl<R, F> SeekableMultiReader<R, F>
+    where R: Read + Seek,
+          F: Fn(usize) -> io::Result<R>
+{
+    /// Creates a chain of sources with the given lengths. `open` is called
+    /// with the index of a source whenever a read needs it.
+    pub fn new<L>(lengths: L, open: F) -> SeekableMultiReader<R, F>
+        where L: IntoIterator<Item = u64>
+    {
+        let mut starts = vec![0];
+        for len in lengths {
+            let end = starts[starts.len() - 1] + len;
+            starts.push(end);
+        }
+        let total = starts[starts.len() - 1];
+        SeekableMultiReader {
+            open,
+            starts: Arc::new(starts),
+            window: 0..total,
+            pos: 0,
+            cache: Vec::new(),
+            max_open: 1,
+            positioned: false,
+        }
+    }
+
+    /// Keeps up to `max` sources open, closing the least recently read one
+    /// to make room. Going back to a source still open is only a seek,
+    /// whereas the others are opened and seeked anew. Only the source being
+    /// read is kept by default.
+    pub fn max_open(mut self, max: usize) -> SeekableMultiReader<R, F> {
+        assert!(max > 0, "a source must be open to be read");
+        self.max_open = max;
+        self
+    }
+
+    /// Number of bytes in the chain.
+    pub fn len(&self) -> u64 {
+        self.window.end - self.window.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of bytes read so far.
+    pub fn position(&self) -> u64 {
+        self.pos - self.window.start
+    }
+
+    /// Skips the next `n` bytes, fewer at the end of the chain, and returns
+    /// the number of bytes skipped. Only the source landed in is opened;
+    /// the sources skipped over entirely are not.
+    pub fn skip_bytes(&mut self, n: u64) -> io::Result<u64> {
+        let left = self.len().saturating_sub(self.position());
+        let skipped = ::std::cmp::min(n, left);
+        self.seek(SeekFrom::Current(skipped as i64))?;
+        Ok(skipped)
+    }
+
+    /// Returns a reader over `range` of this chain, clamped to its length.
+    /// The new reader opens its own sources, so reading it does not affect
+    /// this one and the other way round, and has the same `max_open`.
+    pub fn sub_reader(&self, range: Range<u64>) -> SeekableMultiReader<R, &F> {
+        assert!(range.start <= range.end, "invalid range {:?}", range);
+        let start = ::std::cmp::min(self.window.start + range.start, self.window.end);
+        let end = ::std::cmp::min(self.window.start + range.end, self.window.end);
+        SeekableMultiReader {
+            open: &self.open,
+            starts: self.starts.clone(),
+            window: start..end,
+            pos: start,
+            cache: Vec::new(),
+            max_open: self.max_open,
+            positioned: false,
+        }
+    }
+
+    /// Index of the source containing the byte at `pos`, which must be
+    /// within the chain.
+    fn source_at(&self, pos: u64) -> usize {
+        self.starts.partition_point(|&start| start <= pos) - 1
+    }
+
+    /// Makes source `index` the last in the cache, positioned at `pos`.
+    fn activate(&mut self, index: usize) -> io::Result<()> {
+        if self.positioned && self.cache.last().map(|c| c.0) == Some(index) {
+            return Ok(());
+        }
+        self.positioned = false;
+        let mut r = match self.cache.iter().position(|c| c.0 == index) {
+            Some(i) => self.cache.remove(i).1,
+            None => {
+                if self.cache.len() >= self.max_open {
+                    self.cache.remove(0);
+                }
+                (self.open)(index)?
+            }
+        };
+        r.seek(SeekFrom::Start(self.pos - self.starts[index]))?;
+        self.cache.push((index, r));
+        self.positioned = true;
+        Ok(())
+    }
+}
+
+impl<R, F> Read for SeekableMultiReader<R, F>
+    where R: Read + Seek,
+          F: Fn(usize) -> io::Result<R>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.window.end {
+            return Ok(0);
+        }
+        let index = self.source_at(self.pos);
+        let end = ::std::cmp::min(self.starts[index + 1], self.window.end);
+        self.activate(index)?;
+        let r = &mut self.cache.last_mut().expect("source is open").1;
+        let want = ::std::cmp::min(buf.len() as u64, end - self.pos) as usize;
+        let n = r.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      format!("source {} ended {} bytes early",
+                                              index,
+                                              self.starts[index + 1] - self.pos)));
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R, F> Seek for SeekableMultiReader<R, F>
+    where R: Read + Seek,
+          F: Fn(usize) -> io::Result<R>
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(n) => (n, 0),
+            SeekFrom::End(d) => (self.len(), d),
+            SeekFrom::Current(d) => (self.position(), d),
+        };
+        let target = match base.checked_add_signed(delta) {
+            Some(target) => target,
+            None => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "invalid seek to a negative or overflowing position"))
+            }
+        };
+        self.pos = self.window.start.saturating_add(target);
+        self.positioned = false;
+        if self.pos < self.window.end {
+            let index = self.source_at(self.pos);
+            if let Some(&mut (i, ref mut r)) = self.cache.last_mut() {
+                if i == index {
+                    r.seek(SeekFrom::Start(self.pos - self.starts[index]))?;
+                    self.positioned = true;
+                }
+            }
+        }
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+    use super::SeekableMultiReader;
+
+    const SEGMENTS: [&[u8]; 4] = [b"abc", b"", b"defg", b"hi"];
+
+    fn read_all<R: Read>(mut r: R) -> String {
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_read() {
+        let opened = RefCell::new(Vec::new());
+        let m = SeekableMultiReader::new(SEGMENTS.iter().map(|s| s.len() as u64), |i| {
+            opened.borrow_mut().push(i);
+            Ok(Cursor::new(SEGMENTS[i]))
+        });
+        assert_eq!(m.len(), 9);
+        assert_eq!(read_all(m), "abcdefghi");
+        assert_eq!(*opened.borrow(), vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn test_sub_reader() {
+        let opened = RefCell::new(Vec::new());
+        let mut m = SeekableMultiReader::new(SEGMENTS.iter().map(|s| s.len() as u64), |i| {
+            opened.borrow_mut().push(i);
+            Ok(Cursor::new(SEGMENTS[i]))
+        });
+        assert_eq!(m.read(&mut [0; 2]).unwrap(), 2);
+        {
+            let sub = m.sub_reader(4..20);
+            assert_eq!(sub.len(), 5);
+            assert_eq!(read_all(sub.sub_reader(1..4)), "fgh");
+            assert_eq!(read_all(sub), "efghi");
+        }
+        assert_eq!(*opened.borrow(), vec![0, 2, 3, 2, 3]);
+        assert_eq!(m.position(), 2);
+        assert_eq!(read_all(m), "cdefghi");
+    }
+
+    #[test]
+    fn test_seek() {
+        let opened = RefCell::new(Vec::new());
+        let mut m = SeekableMultiReader::new(SEGMENTS.iter().map(|s| s.len() as u64), |i| {
+            opened.borrow_mut().push(i);
+            Ok(Cursor::new(SEGMENTS[i]))
+        });
+        let mut buf = [0; 2];
+        assert_eq!(m.seek(SeekFrom::Start(4)).unwrap(), 4);
+        m.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ef");
+        // Within the open source.
+        assert_eq!(m.seek(SeekFrom::Current(-3)).unwrap(), 3);
+        m.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"de");
+        // Back into a finished source.
+        assert_eq!(m.seek(SeekFrom::End(-8)).unwrap(), 1);
+        m.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"bc");
+        assert_eq!(*opened.borrow(), vec![2, 0]);
+
+        assert_eq!(m.seek(SeekFrom::Start(20)).unwrap(), 20);
+        assert_eq!(m.read(&mut buf).unwrap(), 0);
+        let err = m.seek(SeekFrom::Current(-21)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_skip_bytes_opens_only_the_target() {
+        let opened = RefCell::new(Vec::new());
+        let mut m = SeekableMultiReader::new((0..10_000).map(|_| 10), |i| {
+            opened.borrow_mut().push(i);
+            Ok(Cursor::new(format!("{:010}", i)))
+        });
+        assert_eq!(m.skip_bytes(90_005).unwrap(), 90_005);
+        let mut buf = [0; 5];
+        m.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"09000");
+        assert_eq!(m.skip_bytes(1 << 40).unwrap(), 9_990);
+        assert_eq!(m.read(&mut buf).unwrap(), 0);
+        assert_eq!(*opened.borrow(), vec![9_000]);
+    }
+
+    #[test]
+    fn test_seek_in_sub_reader() {
+        let m = SeekableMultiReader::new(SEGMENTS.iter().map(|s| s.len() as u64),
+                                         |i| Ok(Cursor::new(SEGMENTS[i])));
+        let mut sub = m.sub_reader(2..6);
+        assert_eq!(sub.seek(SeekFrom::End(-1)).unwrap(), 3);
+        assert_eq!(read_all(&mut sub), "f");
+        sub.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(read_all(sub), "cdef");
+    }
+
+    #[test]
+    fn test_max_open() {
+        let opened = RefCell::new(Vec::new());
+        let mut m = SeekableMultiReader::new(SEGMENTS.iter().map(|s| s.len() as u64), |i| {
+            opened.borrow_mut().push(i);
+            Ok(Cursor::new(SEGMENTS[i]))
+        })
+        .max_open(2);
+        let mut buf = [0; 1];
+        for &(pos, byte) in &[(0, b'a'), (8, b'i'), (1, b'b'), (7, b'h'), (4, b'e'), (2, b'c')] {
+            m.seek(SeekFrom::Start(pos)).unwrap();
+            m.read_exact(&mut buf).unwrap();
+            assert_eq!(buf[0], byte);
+        }
+        assert_eq!(*opened.borrow(), vec![0, 3, 2, 0]);
+        assert_eq!(m.cache.len(), 2);
+        assert_eq!(read_all(m.sub_reader(0..9)), "abcdefghi");
+    }
+
+    #[test]
+    fn test_short_source() {
+        let lengths = vec![3, 5];
+        let m = SeekableMultiReader::new(lengths, |i| Ok(Cursor::new(SEGMENTS[i])));
+        let err = m.sub_reader(2..8).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), "source 1 ended 5 bytes early");
+    }
+}
@@ -0,0 +1,97 @@
+//! A chain drained cooperatively through clonable handles.
+//!
+//! Every read locks the chain, so each returns the next bytes of it and no
+//! byte is returned twice:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use std::thread;
+//! use multi_reader::MultiReader;
+//! use multi_reader::shared::SharedMultiReader;
+//!
+//! fn main() {
+//!     let chain = MultiReader::new(vec![&b"abc"[..], &b"def"[..]].into_iter());
+//!     let queue = SharedMultiReader::new(chain);
+//!     let workers: Vec<_> = (0..2).map(|_| {
+//!         let mut queue = queue.clone();
+//!         thread::spawn(move || {
+//!             let mut items = Vec::new();
+//!             queue.read_to_end(&mut items).unwrap();
+//!             items.len()
+//!         })
+//!     }).collect();
+//!     let total: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+//!     assert_eq!(total, 6);
+//! }
+//! ```
+
+use std::io;
+use std::io::Read;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use MultiReader;
+
+/// A clonable handle over one chain.
+pub struct SharedMultiReader<R, I> {
+    inner: Arc<Mutex<MultiReader<R, I>>>,
+}
+
+impl<R, I> Clone for SharedMultiReader<R, I> {
+    fn clone(&self) -> SharedMultiReader<R, I> {
+        SharedMultiReader { inner: self.inner.clone() }
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> SharedMultiReader<R, I> {
+    pub fn new(reader: MultiReader<R, I>) -> SharedMultiReader<R, I> {
+        SharedMultiReader { inner: Arc::new(Mutex::new(reader)) }
+    }
+
+    /// Locks the chain, for reading several times in a row or inspecting
+    /// it without other handles interleaving. Fails if a reader panicked
+    /// while holding the lock.
+    pub fn lock(&self) -> io::Result<MutexGuard<'_, MultiReader<R, I>>> {
+        self.inner.lock().map_err(|_| io::Error::other("chain poisoned by a panicking reader"))
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Read for SharedMultiReader<R, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock()?.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use std::thread;
+    use super::SharedMultiReader;
+    use MultiReader;
+
+    #[test]
+    fn test_workers_split_the_chain() {
+        let sources: Vec<Vec<u8>> = (0..50u8).map(|i| vec![i; 100]).collect();
+        let shared = SharedMultiReader::new(MultiReader::new(sources.into_iter().map(Cursor::new)));
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut shared = shared.clone();
+                thread::spawn(move || {
+                    let mut got = Vec::new();
+                    let mut buf = [0; 7];
+                    loop {
+                        match shared.read(&mut buf).unwrap() {
+                            0 => return got,
+                            n => got.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                })
+            })
+            .collect();
+        let mut all: Vec<u8> = workers.into_iter().flat_map(|w| w.join().unwrap()).collect();
+        all.sort();
+        let expected: Vec<u8> = (0..50u8).flat_map(|i| vec![i; 100]).collect();
+        assert_eq!(all, expected);
+        assert!(shared.lock().unwrap().is_finished());
+    }
+}
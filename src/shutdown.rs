@@ -0,0 +1,189 @@
+//! Stopping a chain between two reads.
+//!
+//! A `ShutdownHandle` can be sent to another thread, which uses it to end
+//! the chain without interrupting a read in progress, or once the source
+//! being read is done with, so that no source is cut short. The chain
+//! remembers where it stopped, so that reading can be resumed from there
+//! later:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//!
+//! fn main() {
+//!     let mut m = MultiReader::new(vec![&b"abc"[..], &b"def"[..]].into_iter());
+//!     let handle = m.shutdown_handle();
+//!     let mut buf = [0; 2];
+//!     assert_eq!(m.read(&mut buf).unwrap(), 2);
+//!     handle.shutdown();
+//!     assert_eq!(m.read(&mut buf).unwrap(), 0);
+//!     assert_eq!(m.stopped_at(), Some((0, 2)));
+//! }
+//! ```
+
+use std::io;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// Shuts down the chain it was taken from, see
+/// `MultiReader::shutdown_handle`.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    state: Arc<AtomicU8>,
+}
+
+const RUNNING: u8 = 0;
+const AT_BOUNDARY: u8 = 1;
+const STOPPED: u8 = 2;
+
+impl ShutdownHandle {
+    /// Ends the chain once the read in progress, if any, returns.
+    pub fn shutdown(&self) {
+        self.state.store(STOPPED, Ordering::SeqCst);
+    }
+
+    /// Ends the chain once the source being read reaches its end, before
+    /// the next one is opened.
+    pub fn stop_after_current_source(&self) {
+        // Does nothing if already shut down.
+        let _ = self.state.compare_exchange(RUNNING, AT_BOUNDARY, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    /// Whether the chain has stopped, or stops with its next read.
+    pub fn is_shut_down(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == STOPPED
+    }
+}
+
+/// State of the chain for shutting down.
+pub(crate) struct Stop {
+    state: Arc<AtomicU8>,
+    pub(crate) error: Option<io::ErrorKind>,
+    pub(crate) stopped_at: Option<(usize, u64)>,
+}
+
+impl Stop {
+    pub(crate) fn new() -> Stop {
+        Stop {
+            state: Arc::new(AtomicU8::new(RUNNING)),
+            error: None,
+            stopped_at: None,
+        }
+    }
+
+    /// The same settings, for a chain shut down on its own.
+    pub(crate) fn detached(&self) -> Stop {
+        Stop { error: self.error, ..Stop::new() }
+    }
+
+    pub(crate) fn handle(&self) -> ShutdownHandle {
+        ShutdownHandle { state: self.state.clone() }
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == STOPPED
+    }
+
+    /// Whether to stop at the end of the current source, which the chain
+    /// does by shutting down there.
+    pub(crate) fn at_boundary(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == AT_BOUNDARY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use std::thread;
+    use MultiReader;
+    use testing::MaybeErrReader;
+
+    #[test]
+    fn test_shutdown_from_another_thread() {
+        let mut m = MultiReader::new(vec![&b"abc"[..], &b"def"[..]].into_iter());
+        let handle = m.shutdown_handle();
+        let mut buf = [0; 4];
+        assert_eq!(m.read(&mut buf).unwrap(), 3);
+        assert_eq!(m.read(&mut buf[..1]).unwrap(), 1);
+        thread::spawn(move || handle.shutdown()).join().unwrap();
+        assert_eq!(m.read(&mut buf).unwrap(), 0);
+        assert_eq!(m.fill_peek_buffer(1).unwrap(), b"");
+        assert_eq!(m.stopped_at(), Some((1, 1)));
+        assert!(m.shutdown_handle().is_shut_down());
+    }
+
+    #[test]
+    fn test_shutdown_error() {
+        let mut m = MultiReader::new(vec![&b"abc"[..]].into_iter())
+            .shutdown_error(io::ErrorKind::ConnectionAborted);
+        assert_eq!(m.stopped_at(), None);
+        m.shutdown();
+        let err = m.read(&mut [0; 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+        assert_eq!(m.stopped_at(), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_shutdown_after_peek() {
+        let mut m = MultiReader::new(vec![&b"ab\n"[..]].into_iter());
+        assert_eq!(m.fill_peek_buffer(3).unwrap(), b"ab\n");
+        m.shutdown();
+        let mut line = String::new();
+        assert_eq!(m.read_line(&mut line).unwrap(), 0);
+        assert_eq!(line, "");
+
+        let mut m = MultiReader::new(vec![&b"ab\n"[..]].into_iter())
+            .shutdown_error(io::ErrorKind::ConnectionAborted);
+        m.fill_peek_buffer(3).unwrap();
+        m.shutdown();
+        let mut line = b"x".to_vec();
+        assert!(m.read_until(b'\n', &mut line).is_err());
+        assert_eq!(line, b"x");
+    }
+
+    #[test]
+    fn test_stop_after_current_source() {
+        let mut m = MultiReader::new(vec![&b"abc"[..], &b"def"[..]].into_iter());
+        let mut buf = [0; 2];
+        assert_eq!(m.read(&mut buf).unwrap(), 2);
+        m.stop_after_current_source();
+        let mut rest = Vec::new();
+        m.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"c");
+        assert_eq!(m.stopped_at(), Some((0, 3)));
+        assert!(m.shutdown_handle().is_shut_down());
+
+        // Bytes peeked from the next source are not served either.
+        let mut m = MultiReader::new(vec![&b"ab"[..], &b"cd"[..]].into_iter());
+        assert_eq!(m.fill_peek_buffer(3).unwrap(), b"abc");
+        m.stop_after_current_source();
+        let mut rest = Vec::new();
+        m.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"ab");
+        assert_eq!(m.stopped_at(), Some((0, 2)));
+    }
+
+    #[test]
+    fn test_stop_after_current_source_read_line() {
+        let mut m = MultiReader::new(vec![&b"ab"[..], &b"c\nd"[..]].into_iter());
+        assert_eq!(m.fill_peek_buffer(3).unwrap(), b"abc");
+        m.stop_after_current_source();
+        let mut line = String::new();
+        assert_eq!(m.read_line(&mut line).unwrap(), 2);
+        assert_eq!(line, "ab");
+        assert_eq!(m.read_line(&mut line).unwrap(), 0);
+        assert_eq!(m.stopped_at(), Some((0, 2)));
+    }
+
+    #[test]
+    fn test_stop_after_current_source_keeps_next_closed() {
+        let sources = vec![MaybeErrReader::good(&b"ab"[..]), MaybeErrReader::broken(&b""[..], 0)];
+        let mut m = MultiReader::new(sources.into_iter());
+        m.stop_after_current_source();
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"ab");
+    }
+}
@@ -0,0 +1,166 @@
+//! A chain of in-memory buffers whose `fill_buf` hands out the buffers
+//! themselves, without copying.
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::BufRead;
+//! use multi_reader::slices::SliceMultiReader;
+//!
+//! fn main() {
+//!     let header = b"HEAD".to_vec();
+//!     let body = b"body";
+//!     let mut reader = SliceMultiReader::new(vec![&header[..], &b""[..], &body[..]]);
+//!     assert_eq!(reader.fill_buf().unwrap(), b"HEAD");
+//!     reader.consume(4);
+//!     assert_eq!(reader.fill_buf().unwrap().as_ptr(), body.as_ptr());
+//! }
+//! ```
+//!
+//! `from_static` chains assets embedded in the binary, without allocating:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::slices::SliceMultiReader;
+//!
+//! static PAGE: &[&[u8]] = &[b"<pre>", include_bytes!("../README.md"), b"</pre>"];
+//!
+//! fn main() {
+//!     let mut page = String::new();
+//!     SliceMultiReader::from_static(PAGE).read_to_string(&mut page).unwrap();
+//!     assert!(page.starts_with("<pre>"));
+//! }
+//! ```
+
+use std::io;
+use std::io::{BufRead, Read};
+use std::iter;
+use std::slice;
+
+/// Chain of embedded assets, see `SliceMultiReader::from_static`.
+pub type StaticAssets =
+    SliceMultiReader<&'static [u8], iter::Copied<slice::Iter<'static, &'static [u8]>>>;
+
+/// Reads buffers one after another. Any `AsRef<[u8]>` works as a buffer,
+/// such as `&[u8]` or `Vec<u8>`.
+pub struct SliceMultiReader<T, I> {
+    slices: I,
+    current: Option<T>,
+    pos: usize,
+    index: usize,
+}
+
+impl<T: AsRef<[u8]>, I: Iterator<Item = T>> SliceMultiReader<T, I> {
+    pub fn new<S>(slices: S) -> SliceMultiReader<T, I>
+        where S: IntoIterator<Item = T, IntoIter = I>
+    {
+        let mut slices = slices.into_iter();
+        let current = slices.next();
+        SliceMultiReader {
+            slices,
+            current,
+            pos: 0,
+            index: 0,
+        }
+    }
+
+    /// Index of the buffer being read.
+    pub fn source_index(&self) -> usize {
+        self.index
+    }
+
+    /// Skips exhausted buffers, returning the rest of the current one.
+    fn remaining(&mut self) -> &[u8] {
+        loop {
+            let exhausted = match self.current {
+                Some(ref s) => self.pos == s.as_ref().len(),
+                None => return &[],
+            };
+            if !exhausted {
+                break;
+            }
+            self.current = self.slices.next();
+            self.pos = 0;
+            self.index += 1;
+        }
+        match self.current {
+            Some(ref s) => &s.as_ref()[self.pos..],
+            None => &[],
+        }
+    }
+}
+
+impl StaticAssets {
+    /// Chains `assets`, such as `include_bytes!` ones, in order.
+    pub fn from_static(assets: &'static [&'static [u8]]) -> StaticAssets {
+        SliceMultiReader::new(assets.iter().copied())
+    }
+}
+
+impl<T: AsRef<[u8]>, I: Iterator<Item = T>> Read for SliceMultiReader<T, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let n = {
+            let remaining = self.remaining();
+            let n = ::std::cmp::min(buf.len(), remaining.len());
+            buf[..n].copy_from_slice(&remaining[..n]);
+            n
+        };
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<T: AsRef<[u8]>, I: Iterator<Item = T>> BufRead for SliceMultiReader<T, I> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let len = self.current.as_ref().map_or(0, |s| s.as_ref().len());
+        self.pos = ::std::cmp::min(self.pos + amt, len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Read};
+    use super::SliceMultiReader;
+
+    #[test]
+    fn test_fill_buf_does_not_copy() {
+        let sources = vec![b"ab".to_vec(), Vec::new(), b"cde".to_vec()];
+        let ptrs: Vec<_> = sources.iter().map(|s| s.as_ptr()).collect();
+        let mut m = SliceMultiReader::new(sources);
+        assert_eq!(m.fill_buf().unwrap().as_ptr(), ptrs[0]);
+        m.consume(1);
+        assert_eq!(m.fill_buf().unwrap(), b"b");
+        m.consume(1);
+        assert_eq!(m.fill_buf().unwrap().as_ptr(), ptrs[2]);
+        assert_eq!(m.source_index(), 2);
+        m.consume(3);
+        assert!(m.fill_buf().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_from_static() {
+        static ASSETS: &[&[u8]] = &[b"a", b"", b"bc"];
+        let mut m = SliceMultiReader::from_static(ASSETS);
+        assert_eq!(m.fill_buf().unwrap().as_ptr(), ASSETS[0].as_ptr());
+        let mut out = Vec::new();
+        m.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abc");
+        assert_eq!(m.source_index(), 3);
+    }
+
+    #[test]
+    fn test_read_and_lines() {
+        let mut m = SliceMultiReader::new(vec![&b"one\ntw"[..], &b"o\n"[..]]);
+        let mut buf = [0; 2];
+        assert_eq!(m.read(&mut buf).unwrap(), 2);
+        let lines: Vec<String> = m.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["e", "two"]);
+    }
+}
@@ -0,0 +1,414 @@
+//! The files of a tar archive as the sources of a chain.
+//!
+//! The archive is read front to back in one pass: each regular file entry
+//! that `select` keeps becomes a source, in archive order, and the entries
+//! left out are skipped over. Directories, links and other special entries
+//! are never sources.
+//!
+//! ```rust,no_run
+//! extern crate multi_reader;
+//! use std::fs::File;
+//! use std::io::Read;
+//! use multi_reader::tar;
+//!
+//! fn main() {
+//!     let archive = File::open("dataset.tar").unwrap();
+//!     let mut reader = tar::from_tar(archive, |path| path.extension() == Some("csv".as_ref()));
+//!     let mut rows = String::new();
+//!     reader.read_to_string(&mut rows).unwrap();
+//! }
+//! ```
+//!
+//! Both ustar and GNU archives are understood, including GNU long names and
+//! the `path` and `size` records of pax extended headers.
+
+use std::cell::RefCell;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str;
+
+use {Fallible, MultiReader};
+
+const BLOCK: u64 = 512;
+
+/// Chains the contents of the regular files in `archive` whose path
+/// `select` returns `true` for.
+pub fn from_tar<R, F>(archive: R, select: F) -> MultiReader<Fallible<Entry<R>>, Entries<R, F>>
+    where R: Read,
+          F: FnMut(&Path) -> bool
+{
+    MultiReader::new(Entries {
+        archive: Rc::new(RefCell::new(Archive {
+            inner: archive,
+            serial: 0,
+            remaining: 0,
+            padding: 0,
+        })),
+        select,
+        done: false,
+    })
+}
+
+struct Archive<R> {
+    inner: R,
+    // Numbers the entries, so that an entry read after the archive moved
+    // past it reads as empty rather than the bytes of the next one.
+    serial: u64,
+    remaining: u64,
+    padding: u64,
+}
+
+/// The contents of one file in the archive.
+pub struct Entry<R> {
+    archive: Rc<RefCell<Archive<R>>>,
+    serial: u64,
+    path: PathBuf,
+    size: u64,
+}
+
+impl<R: Read> Entry<R> {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl<R: Read> Read for Entry<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut archive = self.archive.borrow_mut();
+        if archive.serial != self.serial || archive.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = ::std::cmp::min(buf.len() as u64, archive.remaining) as usize;
+        let n = archive.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(truncated());
+        }
+        archive.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Source iterator behind `from_tar`.
+pub struct Entries<R, F> {
+    archive: Rc<RefCell<Archive<R>>>,
+    select: F,
+    done: bool,
+}
+
+impl<R: Read, F: FnMut(&Path) -> bool> Iterator for Entries<R, F> {
+    type Item = Fallible<Entry<R>>;
+
+    fn next(&mut self) -> Option<Fallible<Entry<R>>> {
+        if self.done {
+            return None;
+        }
+        match self.next_entry() {
+            Ok(Some(entry)) => Some(Fallible::from(Ok(entry))),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                // The position in the archive is lost.
+                self.done = true;
+                Some(Fallible::from(Err(e)))
+            }
+        }
+    }
+}
+
+impl<R: Read, F: FnMut(&Path) -> bool> Entries<R, F> {
+    fn next_entry(&mut self) -> io::Result<Option<Entry<R>>> {
+        let mut archive = self.archive.borrow_mut();
+        let mut long_path = None;
+        let mut long_size = None;
+        loop {
+            let skip = archive.remaining + archive.padding;
+            archive.remaining = 0;
+            archive.padding = 0;
+            discard(&mut archive.inner, skip)?;
+
+            let mut header = [0; BLOCK as usize];
+            if !read_block(&mut archive.inner, &mut header)? || header.iter().all(|&b| b == 0) {
+                return Ok(None);
+            }
+            check_sum(&header)?;
+            let size = parse_size(&header[124..136])?;
+            archive.serial += 1;
+            archive.remaining = size;
+            archive.padding = (BLOCK - size % BLOCK) % BLOCK;
+            match header[156] {
+                b'L' => {
+                    let name = data(&mut archive, size)?;
+                    long_path = Some(PathBuf::from(string(trim_nul(&name))?));
+                }
+                b'x' => {
+                    for (key, value) in pax_records(&data(&mut archive, size)?)? {
+                        match key {
+                            "path" => long_path = Some(PathBuf::from(value)),
+                            "size" => {
+                                long_size = Some(value.parse().map_err(|_| invalid("bad pax size"))?)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                b'0' | b'\0' | b'7' => {
+                    let path = match long_path.take() {
+                        Some(path) => path,
+                        None => header_path(&header)?,
+                    };
+                    if let Some(size) = long_size.take() {
+                        archive.remaining = size;
+                        archive.padding = (BLOCK - size % BLOCK) % BLOCK;
+                    }
+                    if (self.select)(&path) {
+                        return Ok(Some(Entry {
+                            archive: self.archive.clone(),
+                            serial: archive.serial,
+                            path,
+                            size: archive.remaining,
+                        }));
+                    }
+                }
+                // Directories, links and the like, which any long name or
+                // size was for.
+                _ => {
+                    long_path = None;
+                    long_size = None;
+                }
+            }
+        }
+    }
+}
+
+fn header_path(header: &[u8]) -> io::Result<PathBuf> {
+    let name = string(trim_nul(&header[..100]))?;
+    // GNU headers have "ustar  " here, and times and offsets in place of the
+    // prefix.
+    let prefix = if &header[257..263] == b"ustar\0" {
+        string(trim_nul(&header[345..500]))?
+    } else {
+        ""
+    };
+    Ok(if prefix.is_empty() {
+        PathBuf::from(name)
+    } else {
+        Path::new(prefix).join(name)
+    })
+}
+
+fn check_sum(header: &[u8]) -> io::Result<()> {
+    let expected = parse_octal(&header[148..156])?;
+    let sum: u64 = header.iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 })
+        .sum();
+    if sum != expected {
+        return Err(invalid("tar header checksum mismatch"));
+    }
+    Ok(())
+}
+
+fn parse_size(field: &[u8]) -> io::Result<u64> {
+    // GNU base-256, for sizes that do not fit in octal.
+    if field[0] & 0x80 != 0 {
+        let mut size = (field[0] & 0x7f) as u64;
+        for &b in &field[1..] {
+            if size >> 56 != 0 {
+                return Err(invalid("tar entry size out of range"));
+            }
+            size = size << 8 | b as u64;
+        }
+        return Ok(size);
+    }
+    parse_octal(field)
+}
+
+fn parse_octal(field: &[u8]) -> io::Result<u64> {
+    let digits = string(trim_nul(field))?.trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid("bad octal number in tar header"))
+}
+
+fn pax_records(data: &[u8]) -> io::Result<Vec<(&str, &str)>> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ').ok_or_else(|| invalid("bad pax record"))?;
+        let len: usize = string(&rest[..space])?.parse().map_err(|_| invalid("bad pax record"))?;
+        if len <= space + 1 || len > rest.len() || rest[len - 1] != b'\n' {
+            return Err(invalid("bad pax record"));
+        }
+        let record = string(&rest[space + 1..len - 1])?;
+        let eq = record.find('=').ok_or_else(|| invalid("bad pax record"))?;
+        records.push((&record[..eq], &record[eq + 1..]));
+        rest = &rest[len..];
+    }
+    Ok(records)
+}
+
+/// Reads the data of a metadata entry, such as a long name.
+fn data<R: Read>(archive: &mut Archive<R>, size: u64) -> io::Result<Vec<u8>> {
+    if size > 1024 * 1024 {
+        return Err(invalid("tar metadata entry too large"));
+    }
+    let mut data = vec![0; size as usize];
+    archive.inner.read_exact(&mut data).map_err(|e| if e.kind() == io::ErrorKind::UnexpectedEof {
+        truncated()
+    } else {
+        e
+    })?;
+    archive.remaining = 0;
+    Ok(data)
+}
+
+/// Reads a whole block, returning `false` at a clean end of the archive.
+fn read_block<R: Read>(r: &mut R, block: &mut [u8]) -> io::Result<bool> {
+    let mut got = 0;
+    while got < block.len() {
+        match r.read(&mut block[got..]) {
+            Ok(0) if got == 0 => return Ok(false),
+            Ok(0) => return Err(truncated()),
+            Ok(n) => got += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn discard<R: Read>(r: &mut R, n: u64) -> io::Result<()> {
+    if io::copy(&mut r.take(n), &mut io::sink())? < n {
+        return Err(truncated());
+    }
+    Ok(())
+}
+
+fn trim_nul(field: &[u8]) -> &[u8] {
+    &field[..field.iter().position(|&b| b == 0).unwrap_or(field.len())]
+}
+
+fn string(bytes: &[u8]) -> io::Result<&str> {
+    str::from_utf8(bytes).map_err(|_| invalid("tar header is not valid UTF-8"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "tar archive truncated")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use std::path::Path;
+    use super::from_tar;
+
+    fn entry(archive: &mut Vec<u8>, name: &str, kind: u8, data: &[u8]) {
+        entry_with(archive, name, kind, data, |_| {});
+    }
+
+    fn entry_with<F>(archive: &mut Vec<u8>, name: &str, kind: u8, data: &[u8], fill: F)
+        where F: FnOnce(&mut [u8; 512])
+    {
+        let mut header = [0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        fill(&mut header);
+        header[148..156].copy_from_slice(b"        ");
+        let sum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().div_ceil(512) * 512, 0);
+    }
+
+    fn archive() -> Vec<u8> {
+        let mut a = Vec::new();
+        entry(&mut a, "dir/", b'5', b"");
+        entry(&mut a, "dir/a.csv", b'0', b"1,2\n");
+        entry(&mut a, "dir/notes.txt", b'0', &[b'x'; 600]);
+        let long = format!("{}/b.csv", "d".repeat(120));
+        entry(&mut a, "././@LongLink", b'L', format!("{}\0", long).as_bytes());
+        entry(&mut a, "short", b'0', b"3,4\n");
+        let record = "path=pax/c.csv\n";
+        entry(&mut a, "PaxHeader", b'x', format!("{} {}", record.len() + 3, record).as_bytes());
+        entry(&mut a, "c", b'0', b"5,6\n");
+        a.extend_from_slice(&[0; 1024]);
+        a
+    }
+
+    #[test]
+    fn test_selected_entries() {
+        let mut paths = Vec::new();
+        let mut out = String::new();
+        from_tar(&archive()[..], |p| p.extension() == Some("csv".as_ref()))
+            .map_each(|_, entry| {
+                paths.push(entry.get_ref().unwrap().path().to_owned());
+                entry
+            })
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "1,2\n3,4\n5,6\n");
+        assert_eq!(paths[0], Path::new("dir/a.csv"));
+        assert_eq!(paths[1], Path::new(&"d".repeat(120)).join("b.csv"));
+        assert_eq!(paths[2], Path::new("pax/c.csv"));
+
+        let mut all = Vec::new();
+        from_tar(&archive()[..], |_| true).read_to_end(&mut all).unwrap();
+        assert_eq!(all.len(), 4 + 600 + 4 + 4);
+    }
+
+    #[test]
+    fn test_gnu_header_has_no_prefix() {
+        let mut a = Vec::new();
+        entry_with(&mut a, "g.csv", b'0', b"7\n", |header| {
+            header[257..265].copy_from_slice(b"ustar  \0");
+            // The access time, where a ustar header has its prefix.
+            header[345..357].copy_from_slice(b"14350146457\0");
+        });
+        a.extend_from_slice(&[0; 1024]);
+        let mut paths = Vec::new();
+        let mut out = String::new();
+        from_tar(&a[..], |_| true)
+            .map_each(|_, entry| {
+                paths.push(entry.get_ref().unwrap().path().to_owned());
+                entry
+            })
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "7\n");
+        assert_eq!(paths, vec![Path::new("g.csv")]);
+    }
+
+    #[test]
+    fn test_broken_archives() {
+        let a = archive();
+        let mut out = Vec::new();
+        let err = from_tar(&a[..1000], |_| true).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut bad = a.clone();
+        bad[600] ^= 1;
+        let mut m = from_tar(&bad[..], |_| true);
+        let err = m.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(m.read_to_end(&mut out).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
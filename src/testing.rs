@@ -0,0 +1,331 @@
+//! Misbehaving readers for testing code built around a chain.
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//! use multi_reader::testing::{MaybeErrReader, ShortReader};
+//!
+//! fn main() {
+//!     let sources: Vec<Box<dyn Read>> = vec![
+//!         Box::new(ShortReader::new(&b"abc"[..], 1)),
+//!         Box::new(MaybeErrReader::broken(&b"def"[..], 0)),
+//!     ];
+//!     let mut reader = MultiReader::new(sources.into_iter());
+//!     let mut buf = [0; 8];
+//!     assert_eq!(reader.read(&mut buf).unwrap(), 1);
+//!     let mut rest = Vec::new();
+//!     assert!(reader.read_to_end(&mut rest).is_err());
+//!     assert_eq!(rest, b"bc");
+//! }
+//! ```
+//!
+//! `Chaos` combines all of these at random, reproducibly for a given seed.
+//! Wrap the whole chain in it, or every source through `map_each`:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//! use multi_reader::testing::Chaos;
+//!
+//! fn main() {
+//!     let sources = vec![&b"hello "[..], &b"world"[..]];
+//!     let mut reader = MultiReader::new(sources.into_iter())
+//!         .map_each(|i, r| Chaos::new(r, 42 + i as u64));
+//!     let mut s = String::new();
+//!     // read_to_string retries interrupted reads.
+//!     reader.read_to_string(&mut s).unwrap();
+//!     assert_eq!(s, "hello world");
+//! }
+//! ```
+
+use std::io;
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+
+use rng::XorShift;
+
+/// Fails one chosen read and passes all the others through.
+pub struct MaybeErrReader<R> {
+    reader: R,
+    read_no: usize,
+    fail_at: Option<usize>,
+    kind: io::ErrorKind,
+    message: String,
+}
+
+impl<R: Read> MaybeErrReader<R> {
+    /// A reader that never fails.
+    pub fn good(reader: R) -> MaybeErrReader<R> {
+        MaybeErrReader {
+            reader,
+            read_no: 0,
+            fail_at: None,
+            kind: io::ErrorKind::Other,
+            message: "I'm broken".to_string(),
+        }
+    }
+
+    /// A reader whose read number `fail_at`, counting from zero, fails.
+    pub fn broken(reader: R, fail_at: usize) -> MaybeErrReader<R> {
+        let mut r = MaybeErrReader::good(reader);
+        r.fail_at = Some(fail_at);
+        r
+    }
+
+    /// Sets the error returned by the failing read.
+    pub fn with_error(mut self, kind: io::ErrorKind, message: &str) -> MaybeErrReader<R> {
+        self.kind = kind;
+        self.message = message.to_string();
+        self
+    }
+
+    /// Number of reads attempted so far.
+    pub fn reads(&self) -> usize {
+        self.read_no
+    }
+}
+
+impl<R: Read> Read for MaybeErrReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_no = self.read_no;
+        self.read_no += 1;
+        if self.fail_at == Some(read_no) {
+            Err(io::Error::new(self.kind, self.message.clone()))
+        } else {
+            self.reader.read(buf)
+        }
+    }
+}
+
+/// Returns at most `max` bytes per read.
+pub struct ShortReader<R> {
+    reader: R,
+    max: usize,
+}
+
+impl<R: Read> ShortReader<R> {
+    pub fn new(reader: R, max: usize) -> ShortReader<R> {
+        assert!(max > 0, "a short reader must return at least one byte");
+        ShortReader { reader, max }
+    }
+}
+
+impl<R: Read> Read for ShortReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = ::std::cmp::min(buf.len(), self.max);
+        self.reader.read(&mut buf[..n])
+    }
+}
+
+/// Sleeps before every read.
+pub struct SlowReader<R> {
+    reader: R,
+    delay: Duration,
+}
+
+impl<R: Read> SlowReader<R> {
+    pub fn new(reader: R, delay: Duration) -> SlowReader<R> {
+        SlowReader { reader, delay }
+    }
+}
+
+impl<R: Read> Read for SlowReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        thread::sleep(self.delay);
+        self.reader.read(buf)
+    }
+}
+
+/// Repeats a byte pattern forever.
+pub struct InfiniteReader {
+    pattern: Vec<u8>,
+    pos: usize,
+}
+
+impl InfiniteReader {
+    pub fn new(pattern: &[u8]) -> InfiniteReader {
+        assert!(!pattern.is_empty(), "the pattern must not be empty");
+        InfiniteReader {
+            pattern: pattern.to_vec(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for InfiniteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for b in buf.iter_mut() {
+            *b = self.pattern[self.pos];
+            self.pos = (self.pos + 1) % self.pattern.len();
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Injects short reads, `Interrupted` errors and delays at random, driven
+/// by a seeded PRNG so that every run with the same seed misbehaves the
+/// same way.
+pub struct Chaos<R> {
+    reader: R,
+    rng: XorShift,
+    short_reads: f64,
+    interruptions: f64,
+    delays: f64,
+    max_delay: Duration,
+}
+
+impl<R: Read> Chaos<R> {
+    /// Defaults to short reads 30% and interruptions 10% of the time, with
+    /// no delays.
+    pub fn new(reader: R, seed: u64) -> Chaos<R> {
+        Chaos {
+            reader,
+            rng: XorShift::new(seed),
+            short_reads: 0.3,
+            interruptions: 0.1,
+            delays: 0.0,
+            max_delay: Duration::from_millis(0),
+        }
+    }
+
+    /// Probability of a read being cut to a random shorter length.
+    pub fn short_reads(mut self, p: f64) -> Chaos<R> {
+        self.short_reads = p;
+        self
+    }
+
+    /// Probability of a read failing with `Interrupted`.
+    pub fn interruptions(mut self, p: f64) -> Chaos<R> {
+        self.interruptions = p;
+        self
+    }
+
+    /// Probability of a read being delayed by up to `max`.
+    pub fn delays(mut self, p: f64, max: Duration) -> Chaos<R> {
+        self.delays = p;
+        self.max_delay = max;
+        self
+    }
+}
+
+impl<R: Read> Read for Chaos<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.rng.chance(self.interruptions) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "chaos"));
+        }
+        if self.rng.chance(self.delays) {
+            let max = self.max_delay.as_micros() as u64;
+            thread::sleep(Duration::from_micros(self.rng.below(max + 1)));
+        }
+        let mut n = buf.len();
+        if n > 1 && self.rng.chance(self.short_reads) {
+            n = 1 + self.rng.below(n as u64 - 1) as usize;
+        }
+        self.reader.read(&mut buf[..n])
+    }
+}
+
+/// A fresh temporary directory with a file for each `(name, contents)`,
+/// for the tests of modules reading files.
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str, files: &[(&str, &[u8])]) -> ::std::path::PathBuf {
+    use std::env;
+    use std::fs;
+    let dir = env::temp_dir().join(format!("multi_reader_{}_{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for &(file, contents) in files {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+    use super::{Chaos, InfiniteReader, MaybeErrReader, ShortReader, SlowReader};
+    use MultiReader;
+
+    fn trace(seed: u64) -> (Vec<u8>, Vec<Option<usize>>) {
+        let sources: Vec<Vec<u8>> = (0..5).map(|i| vec![i; 1000]).collect();
+        let mut m = Chaos::new(MultiReader::new(sources.iter().map(|s| &s[..])), seed)
+            .delays(0.1, Duration::from_micros(50));
+        let mut data = Vec::new();
+        let mut reads = Vec::new();
+        let mut buf = [0; 64];
+        loop {
+            match m.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    data.extend_from_slice(&buf[..n]);
+                    reads.push(Some(n));
+                }
+                Err(e) => {
+                    assert_eq!(e.kind(), io::ErrorKind::Interrupted);
+                    reads.push(None);
+                }
+            }
+        }
+        (data, reads)
+    }
+
+    #[test]
+    fn test_maybe_err_reader() {
+        let mut r = MaybeErrReader::broken(&b"abcd"[..], 1)
+            .with_error(io::ErrorKind::Interrupted, "again");
+        let mut buf = [0; 2];
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        let err = r.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(err.to_string(), "again");
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        assert_eq!(r.reads(), 3);
+    }
+
+    #[test]
+    fn test_short_reader() {
+        let mut r = ShortReader::new(&b"abcde"[..], 2);
+        let mut buf = [0; 8];
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        assert_eq!(r.read(&mut buf).unwrap(), 1);
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_slow_reader() {
+        let start = Instant::now();
+        let mut r = SlowReader::new(&b"a"[..], Duration::from_millis(20));
+        assert_eq!(r.read(&mut [0; 4]).unwrap(), 1);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_chaos_is_deterministic() {
+        let (data, reads) = trace(7);
+        let expected: Vec<u8> = (0..5).flat_map(|i| vec![i; 1000]).collect();
+        assert_eq!(data, expected);
+        assert!(reads.contains(&None));
+        // Without short reads every source takes exactly 16 reads.
+        assert!(reads.iter().filter(|r| r.is_some()).count() > 5 * 16);
+        assert_eq!(trace(7), (data, reads.clone()));
+        assert_ne!(trace(8).1, reads);
+    }
+
+    #[test]
+    fn test_infinite_reader() {
+        let mut buf = [0; 7];
+        let mut r = InfiniteReader::new(b"abc");
+        assert_eq!(r.read(&mut buf).unwrap(), 7);
+        assert_eq!(&buf, b"abcabca");
+        assert_eq!(r.read(&mut buf[..2]).unwrap(), 2);
+        assert_eq!(&buf[..2], b"bc");
+    }
+}
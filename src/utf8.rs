@@ -0,0 +1,376 @@
+//! Checking that a chain is valid UTF-8 as it is read.
+//!
+//! `MultiReader::validate_utf8` passes the bytes of the chain on while
+//! checking them, sequences split between two sources included, and fails
+//! at the first invalid one, telling which source it is in:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use std::io::Read;
+//! use multi_reader::MultiReader;
+//!
+//! fn main() {
+//!     let sources = vec![&b"caf\xc3"[..], &b"\xa9\n"[..], &b"na\xefve"[..]];
+//!     let mut reader = MultiReader::new(sources.into_iter()).validate_utf8();
+//!     let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+//!     assert_eq!(err.to_string(), "invalid UTF-8 in source 2 at offset 2");
+//!     assert_eq!(reader.first_invalid(), Some((2, 2)));
+//! }
+//! ```
+//!
+//! `MultiReader::chars` decodes the chain instead, yielding an error for
+//! every invalid sequence and going on after it:
+//!
+//! ```rust
+//! extern crate multi_reader;
+//! use multi_reader::MultiReader;
+//! use multi_reader::utf8::DecodeError;
+//!
+//! fn main() {
+//!     let sources = vec![&b"\xe2\x82"[..], &b"\xac\xff!"[..]];
+//!     let mut chars = MultiReader::new(sources.into_iter()).chars();
+//!     assert_eq!(chars.next().unwrap().unwrap(), '€');
+//!     match chars.next().unwrap() {
+//!         Err(DecodeError::Invalid { source_index, source_offset }) => {
+//!             assert_eq!((source_index, source_offset), (1, 1));
+//!         }
+//!         other => panic!("unexpected {:?}", other),
+//!     }
+//!     assert_eq!(chars.next().unwrap().unwrap(), '!');
+//!     assert!(chars.next().is_none());
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::str;
+
+use pool;
+use MultiReader;
+
+/// Reader behind `MultiReader::validate_utf8`.
+pub struct ValidateUtf8<R, I> {
+    inner: MultiReader<R, I>,
+    // The start of a sequence the last read ended within.
+    pending: [u8; 4],
+    pending_len: usize,
+    pending_at: (usize, u64),
+    invalid: Option<(usize, u64)>,
+}
+
+impl<R: Read, I: Iterator<Item = R>> ValidateUtf8<R, I> {
+    pub fn new(inner: MultiReader<R, I>) -> ValidateUtf8<R, I> {
+        ValidateUtf8 {
+            inner,
+            pending: [0; 4],
+            pending_len: 0,
+            pending_at: (0, 0),
+            invalid: None,
+        }
+    }
+
+    /// Source index and offset of the first invalid sequence, once found.
+    pub fn first_invalid(&self) -> Option<(usize, u64)> {
+        self.invalid
+    }
+
+    pub fn get_ref(&self) -> &MultiReader<R, I> {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> MultiReader<R, I> {
+        self.inner
+    }
+
+    /// Checks the `bytes` read from offset `start` of source `index`,
+    /// returning how many of them come before an invalid sequence, if any.
+    fn check(&mut self, bytes: &[u8], index: usize, start: u64) -> Option<usize> {
+        let mut rest = bytes;
+        if self.pending_len > 0 {
+            let need = sequence_len(self.pending[0]) - self.pending_len;
+            let take = ::std::cmp::min(need, rest.len());
+            let mut seq = self.pending;
+            seq[self.pending_len..self.pending_len + take].copy_from_slice(&rest[..take]);
+            match str::from_utf8(&seq[..self.pending_len + take]) {
+                Ok(_) => self.pending_len = 0,
+                Err(ref e) if e.error_len().is_none() => {
+                    self.pending = seq;
+                    self.pending_len += take;
+                    return None;
+                }
+                Err(_) => {
+                    self.invalid = Some(self.pending_at);
+                    return Some(0);
+                }
+            }
+            rest = &rest[take..];
+        }
+        let skipped = bytes.len() - rest.len();
+        match str::from_utf8(rest) {
+            Ok(_) => None,
+            Err(e) => {
+                let at = skipped + e.valid_up_to();
+                if e.error_len().is_some() {
+                    self.invalid = Some((index, start + at as u64));
+                    return Some(at);
+                }
+                self.pending_len = bytes.len() - at;
+                self.pending[..self.pending_len].copy_from_slice(&bytes[at..]);
+                self.pending_at = (index, start + at as u64);
+                None
+            }
+        }
+    }
+
+    fn error(&self) -> io::Error {
+        let (index, offset) = self.invalid.unwrap_or(self.pending_at);
+        let msg = format!("invalid UTF-8 in source {} at offset {}", index, offset);
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+}
+
+/// Length of the sequence started by `byte`, or 1 since it cannot start one.
+fn sequence_len(byte: u8) -> usize {
+    match byte {
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => 1,
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Read for ValidateUtf8<R, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.invalid.is_some() {
+            return Err(self.error());
+        }
+        let n = self.inner.read(buf)?;
+        if n == 0 {
+            if buf.is_empty() || self.pending_len == 0 {
+                return Ok(0);
+            }
+            // The chain ends within a sequence.
+            self.invalid = Some(self.pending_at);
+            return Err(self.error());
+        }
+        // The bytes of a read all come from the source reported last.
+        let (index, end) = (self.inner.source_index(), self.inner.source_offset());
+        // Separator bytes are at offset 0 of the source they come before.
+        match self.check(&buf[..n], index, end.saturating_sub(n as u64)) {
+            Some(0) => Err(self.error()),
+            Some(valid) => Ok(valid),
+            None => Ok(n),
+        }
+    }
+}
+
+/// Why `Chars` could not decode a char.
+#[derive(Debug)]
+pub enum DecodeError {
+    Io(io::Error),
+    /// A sequence starting at `source_offset` of source `source_index` is
+    /// not valid UTF-8, or the chain ends within it.
+    Invalid { source_index: usize, source_offset: u64 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Io(ref e) => e.fmt(f),
+            DecodeError::Invalid { source_index, source_offset } => {
+                write!(f, "invalid UTF-8 in source {} at offset {}", source_index, source_offset)
+            }
+        }
+    }
+}
+
+impl error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            DecodeError::Io(ref e) => Some(e),
+            DecodeError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Iterator behind `MultiReader::chars`.
+pub struct Chars<R, I> {
+    inner: MultiReader<R, I>,
+    buf: Vec<u8>,
+    pos: usize,
+    filled: usize,
+    // Where the buffered bytes, all of one source, start.
+    index: usize,
+    start: u64,
+}
+
+impl<R: Read, I: Iterator<Item = R>> Chars<R, I> {
+    pub fn new(inner: MultiReader<R, I>) -> Chars<R, I> {
+        Chars {
+            inner,
+            buf: vec![0; pool::DEFAULT_SIZE],
+            pos: 0,
+            filled: 0,
+            index: 0,
+            start: 0,
+        }
+    }
+
+    /// Source index and offset of the next byte to be decoded.
+    pub fn position(&self) -> (usize, u64) {
+        if self.pos == self.filled {
+            return (self.inner.source_index(), self.inner.source_offset());
+        }
+        (self.index, self.start + self.pos as u64)
+    }
+
+    pub fn into_inner(self) -> MultiReader<R, I> {
+        self.inner
+    }
+
+    /// The next byte, without consuming it, along with where it is.
+    fn peek(&mut self) -> Option<io::Result<(u8, usize, u64)>> {
+        while self.pos == self.filled {
+            match self.inner.read(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(n) => {
+                    self.pos = 0;
+                    self.filled = n;
+                    // The bytes of a read all come from the source reported last.
+                    self.index = self.inner.source_index();
+                    // Separator bytes are at offset 0 of the source they come before.
+                    self.start = self.inner.source_offset().saturating_sub(n as u64);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok((self.buf[self.pos], self.index, self.start + self.pos as u64)))
+    }
+}
+
+impl<R: Read, I: Iterator<Item = R>> Iterator for Chars<R, I> {
+    type Item = Result<char, DecodeError>;
+
+    fn next(&mut self) -> Option<Result<char, DecodeError>> {
+        let (first, source_index, source_offset) = match self.peek()? {
+            Ok(byte) => byte,
+            Err(e) => return Some(Err(DecodeError::Io(e))),
+        };
+        self.pos += 1;
+        let invalid = DecodeError::Invalid { source_index, source_offset };
+        let len = sequence_len(first);
+        if first < 0x80 {
+            return Some(Ok(first as char));
+        }
+        if len == 1 {
+            return Some(Err(invalid));
+        }
+        let mut seq = [first, 0, 0, 0];
+        for k in 1..len {
+            let byte = match self.peek() {
+                Some(Ok((byte, _, _))) => byte,
+                Some(Err(e)) => return Some(Err(DecodeError::Io(e))),
+                None => return Some(Err(invalid)),
+            };
+            seq[k] = byte;
+            if let Err(e) = str::from_utf8(&seq[..k + 1]) {
+                if e.error_len().is_some() {
+                    // The byte may start the next char.
+                    return Some(Err(invalid));
+                }
+            }
+            self.pos += 1;
+        }
+        match str::from_utf8(&seq[..len]) {
+            Ok(s) => s.chars().next().map(Ok),
+            Err(_) => Some(Err(invalid)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use super::DecodeError;
+    use testing::MaybeErrReader;
+    use MultiReader;
+
+    fn validate(sources: Vec<&[u8]>) -> (Vec<u8>, Option<(usize, u64)>) {
+        validate_chain(MultiReader::new(sources.into_iter()))
+    }
+
+    fn validate_chain<'a, I>(m: MultiReader<&'a [u8], I>) -> (Vec<u8>, Option<(usize, u64)>)
+        where I: Iterator<Item = &'a [u8]>
+    {
+        let mut m = m.validate_utf8();
+        let mut out = Vec::new();
+        let _ = m.read_to_end(&mut out);
+        (out, m.first_invalid())
+    }
+
+    #[test]
+    fn test_split_sequences() {
+        let (out, invalid) = validate(vec![&b"\xe2"[..], &b""[..], &b"\x82"[..], &b"\xac!"[..]]);
+        assert_eq!(out, "€!".as_bytes());
+        assert_eq!(invalid, None);
+    }
+
+    #[test]
+    fn test_invalid_sequences() {
+        // A valid prefix is passed on.
+        assert_eq!(validate(vec![&b"ok\xff"[..]]), (b"ok".to_vec(), Some((0, 2))));
+        // A sequence started in one source and broken in the next one.
+        assert_eq!(validate(vec![&b"a\xe2\x82"[..], &b"b"[..]]), (b"a\xe2\x82".to_vec(), Some((0, 1))));
+        // The chain ends within a sequence.
+        assert_eq!(validate(vec![&b"a"[..], &b"\xf0\x9f"[..]]), (b"a\xf0\x9f".to_vec(), Some((1, 0))));
+        // Overlong encodings and surrogates are invalid.
+        assert_eq!(validate(vec![&b"\xc0\xaf"[..]]).1, Some((0, 0)));
+        assert_eq!(validate(vec![&b"x"[..], &b"\xed\xa0\x80"[..]]).1, Some((1, 0)));
+    }
+
+    #[test]
+    fn test_chars() {
+        let sources = vec![&b"a\xf0\x9f"[..], &b""[..], &b"\x98\x80\xe2\x82"[..], &b"b\xc3"[..]];
+        let mut chars = MultiReader::new(sources.into_iter()).chars();
+        let mut got = Vec::new();
+        for c in chars.by_ref() {
+            got.push(c.map_err(|e| e.to_string()));
+        }
+        assert_eq!(got, vec![
+            Ok('a'),
+            Ok('😀'),
+            Err("invalid UTF-8 in source 2 at offset 2".to_string()),
+            Ok('b'),
+            Err("invalid UTF-8 in source 3 at offset 1".to_string()),
+        ]);
+        assert_eq!(chars.position(), (4, 0));
+    }
+
+    #[test]
+    fn test_chars_io_errors() {
+        let sources = vec![MaybeErrReader::broken(&b"\xc3\xa9"[..], 0)];
+        let mut chars = MultiReader::new(sources.into_iter()).chars();
+        match chars.next() {
+            Some(Err(DecodeError::Io(_))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(chars.next().unwrap().unwrap(), 'é');
+        assert!(chars.next().is_none());
+    }
+
+    #[test]
+    fn test_invalid_separators() {
+        let m = MultiReader::new(vec![&b"a"[..], &b"b"[..]].into_iter()).separator(b"\xff");
+        assert_eq!(validate_chain(m), (b"a".to_vec(), Some((1, 0))));
+    }
+
+    #[test]
+    fn test_chars_across_separators() {
+        let m = MultiReader::new(vec![&b"a"[..], &b"b"[..]].into_iter()).separator("—".as_bytes());
+        let s: String = m.chars().map(|c| c.unwrap()).collect();
+        assert_eq!(s, "a—b");
+    }
+}